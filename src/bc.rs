@@ -0,0 +1,769 @@
+//! CPU decoder for BC1–BC7, used when the adapter lacks
+//! `Features::TEXTURE_COMPRESSION_BC`.
+
+use wgpu::TextureFormat as F;
+
+/// Uncompressed format [`decode`] produces for `format`, or `None` if it
+/// isn't a BC format. BC6H decodes to half floats and the signed BC4/BC5
+/// variants to `Rgba8Snorm`; everything else to `Rgba8Unorm(Srgb)`.
+pub fn decoded_format(format: F) -> Option<F> {
+    Some(match format {
+        F::Bc1RgbaUnorm | F::Bc2RgbaUnorm | F::Bc3RgbaUnorm | F::Bc7RgbaUnorm => F::Rgba8Unorm,
+        F::Bc1RgbaUnormSrgb | F::Bc2RgbaUnormSrgb | F::Bc3RgbaUnormSrgb | F::Bc7RgbaUnormSrgb => {
+            F::Rgba8UnormSrgb
+        }
+        F::Bc4RUnorm | F::Bc5RgUnorm => F::Rgba8Unorm,
+        F::Bc4RSnorm | F::Bc5RgSnorm => F::Rgba8Snorm,
+        F::Bc6hRgbUfloat | F::Bc6hRgbFloat => F::Rgba16Float,
+        _ => return None,
+    })
+}
+
+/// Decodes one `width` x `height` image of `format` blocks into tightly
+/// packed texels of [`decoded_format`].
+pub fn decode(format: F, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    let decoded = decoded_format(format).expect("not a BC format");
+    let texel_size = decoded.block_size(None).unwrap() as usize;
+    let block_size = format.block_size(None).unwrap() as usize;
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = width.div_ceil(4);
+    let row = width * texel_size;
+    let mut out = vec![0; row * height];
+
+    for (index, block) in data
+        .chunks_exact(block_size)
+        .take(blocks_x * height.div_ceil(4))
+        .enumerate()
+    {
+        let texels = decode_block(format, block);
+        let (bx, by) = (index % blocks_x * 4, index / blocks_x * 4);
+        for (i, texel) in texels.chunks_exact(texel_size).enumerate() {
+            let (x, y) = (bx + i % 4, by + i / 4);
+            if x < width && y < height {
+                let offset = y * row + x * texel_size;
+                out[offset..offset + texel_size].copy_from_slice(texel);
+            }
+        }
+    }
+    out
+}
+
+/// 16 texels, row-major, in the decoded format.
+fn decode_block(format: F, block: &[u8]) -> Vec<u8> {
+    match format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => colour_block(block, true).concat(),
+        F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => {
+            let mut texels = colour_block(&block[8..], false);
+            let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (4 * i)) & 0xf) as u8 * 17;
+            }
+            texels.concat()
+        }
+        F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => {
+            let mut texels = colour_block(&block[8..], false);
+            for (texel, alpha) in texels.iter_mut().zip(unorm_channel(&block[..8])) {
+                texel[3] = alpha;
+            }
+            texels.concat()
+        }
+        F::Bc4RUnorm => unorm_channel(block)
+            .iter()
+            .flat_map(|&r| [r, 0, 0, 255])
+            .collect(),
+        F::Bc5RgUnorm => unorm_channel(&block[..8])
+            .iter()
+            .zip(unorm_channel(&block[8..]))
+            .flat_map(|(&r, g)| [r, g, 0, 255])
+            .collect(),
+        F::Bc4RSnorm => snorm_channel(block)
+            .iter()
+            .flat_map(|&r| [r as u8, 0, 0, 127])
+            .collect(),
+        F::Bc5RgSnorm => snorm_channel(&block[..8])
+            .iter()
+            .zip(snorm_channel(&block[8..]))
+            .flat_map(|(&r, g)| [r as u8, g as u8, 0, 127])
+            .collect(),
+        F::Bc6hRgbUfloat | F::Bc6hRgbFloat => bc6h_block(block, format == F::Bc6hRgbFloat)
+            .iter()
+            .flat_map(|texel| texel.iter().flat_map(|v| v.to_le_bytes()))
+            .collect(),
+        F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => bc7_block(block).concat(),
+        _ => unreachable!(),
+    }
+}
+
+/// Little-endian bit stream over one 128-bit block.
+struct Bits(u128);
+
+impl Bits {
+    fn new(block: &[u8]) -> Self {
+        Self(u128::from_le_bytes(block.try_into().unwrap()))
+    }
+
+    fn take(&mut self, count: u32) -> u32 {
+        let value = (self.0 & ((1 << count) - 1)) as u32;
+        self.0 >>= count;
+        value
+    }
+}
+
+fn rgb565(c: u16) -> [u8; 3] {
+    let (r, g, b) = ((c >> 11) as u8, (c >> 5 & 0x3f) as u8, (c & 0x1f) as u8);
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+/// The BC1 colour block, also used by BC2 and BC3 (which always take the
+/// four-colour mode).
+fn colour_block(block: &[u8], bc1: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+    let mix = |a: u8, b: u8, wa: u16, wb: u16| ((a as u16 * wa + b as u16 * wb) / (wa + wb)) as u8;
+    let blend = |wa, wb| [0, 1, 2].map(|i| mix(e0[i], e1[i], wa, wb));
+
+    let [r0, g0, b0] = e0;
+    let [r1, g1, b1] = e1;
+    let palette = if c0 > c1 || !bc1 {
+        let ([r2, g2, b2], [r3, g3, b3]) = (blend(2, 1), blend(1, 2));
+        [
+            [r0, g0, b0, 255],
+            [r1, g1, b1, 255],
+            [r2, g2, b2, 255],
+            [r3, g3, b3, 255],
+        ]
+    } else {
+        let [r2, g2, b2] = blend(1, 1);
+        [
+            [r0, g0, b0, 255],
+            [r1, g1, b1, 255],
+            [r2, g2, b2, 255],
+            [0; 4],
+        ]
+    };
+
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    std::array::from_fn(|i| palette[(indices >> (2 * i) & 3) as usize])
+}
+
+/// 3-bit indices of a BC3 alpha / BC4 / BC5 channel block.
+fn channel_indices(block: &[u8]) -> [usize; 16] {
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let bits = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| (bits >> (3 * i) & 7) as usize)
+}
+
+fn unorm_channel(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+    }
+    channel_indices(block).map(|i| palette[i] as u8)
+}
+
+fn snorm_channel(block: &[u8]) -> [i8; 16] {
+    // -128 is an alias for -127.
+    let (a0, a1) = (
+        (block[0] as i8).max(-127) as i32,
+        (block[1] as i8).max(-127) as i32,
+    );
+    let mut palette = [a0, a1, 0, 0, 0, 0, -127, 127];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * a0 + i as i32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as i32) * a0 + i as i32 * a1) / 5;
+        }
+    }
+    channel_indices(block).map(|i| palette[i] as i8)
+}
+
+/// Subset 1 membership of each texel, one bit per texel, shared by BC6H
+/// (first 32 entries) and BC7.
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, //
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000, //
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce, //
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, //
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a, //
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660, //
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, //
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Subset of each texel, two bits per texel.
+const PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// Anchor texel of subset 1 in two-subset partitions.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, //
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2, //
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, //
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor texels of subsets 1 and 2 in three-subset partitions.
+const ANCHORS_3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, //
+        3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15, //
+        8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, //
+        3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, //
+        15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8, //
+        15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, //
+        15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(bits: u32) -> &'static [u32] {
+    match bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+/// Subset of texel `i` and whether it's its subset's anchor.
+fn subset(subsets: usize, partition: usize, i: usize) -> (usize, bool) {
+    match subsets {
+        1 => (0, i == 0),
+        2 => {
+            let s = (PARTITIONS_2[partition] >> i & 1) as usize;
+            (s, i == [0, ANCHORS_2[partition] as usize][s])
+        }
+        _ => {
+            let s = (PARTITIONS_3[partition] >> (2 * i) & 3) as usize;
+            let anchors = [
+                0,
+                ANCHORS_3[0][partition] as usize,
+                ANCHORS_3[1][partition] as usize,
+            ];
+            (s, i == anchors[s])
+        }
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    colour_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index_bits_2: u32,
+}
+
+const fn bc7_mode(
+    subsets: usize,
+    [partition_bits, rotation_bits, index_selection_bits]: [u32; 3],
+    [colour_bits, alpha_bits]: [u32; 2],
+    [endpoint_pbits, shared_pbits]: [bool; 2],
+    [index_bits, index_bits_2]: [u32; 2],
+) -> Bc7Mode {
+    Bc7Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        colour_bits,
+        alpha_bits,
+        endpoint_pbits,
+        shared_pbits,
+        index_bits,
+        index_bits_2,
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode(3, [4, 0, 0], [4, 0], [true, false], [3, 0]),
+    bc7_mode(2, [6, 0, 0], [6, 0], [false, true], [3, 0]),
+    bc7_mode(3, [6, 0, 0], [5, 0], [false, false], [2, 0]),
+    bc7_mode(2, [6, 0, 0], [7, 0], [true, false], [2, 0]),
+    bc7_mode(1, [0, 2, 1], [5, 6], [false, false], [2, 3]),
+    bc7_mode(1, [0, 2, 0], [7, 8], [false, false], [2, 2]),
+    bc7_mode(1, [0, 0, 0], [7, 7], [true, false], [4, 0]),
+    bc7_mode(2, [6, 0, 0], [5, 5], [true, false], [2, 0]),
+];
+
+fn bc7_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let mode_index = block[0].trailing_zeros() as usize;
+    let Some(mode) = BC7_MODES.get(mode_index) else {
+        // Reserved mode 8.
+        return [[0; 4]; 16];
+    };
+    let mut bits = Bits::new(block);
+    bits.take(mode_index as u32 + 1);
+    let partition = bits.take(mode.partition_bits) as usize;
+    let rotation = bits.take(mode.rotation_bits);
+    let index_selection = bits.take(mode.index_selection_bits) != 0;
+
+    // endpoints[subset * 2 + end][channel], before p-bits and expansion.
+    let ends = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..4 {
+        let channel_bits = if channel < 3 {
+            mode.colour_bits
+        } else {
+            mode.alpha_bits
+        };
+        for endpoint in &mut endpoints[..ends] {
+            endpoint[channel] = bits.take(channel_bits);
+        }
+    }
+
+    let mut precision = [
+        mode.colour_bits,
+        mode.colour_bits,
+        mode.colour_bits,
+        mode.alpha_bits,
+    ];
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let pbits: Vec<u32> = if mode.endpoint_pbits {
+            (0..ends).map(|_| bits.take(1)).collect()
+        } else {
+            (0..mode.subsets).flat_map(|_| [bits.take(1); 2]).collect()
+        };
+        for (endpoint, pbit) in endpoints[..ends].iter_mut().zip(pbits) {
+            for (value, &bits) in endpoint.iter_mut().zip(&precision) {
+                if bits > 0 {
+                    *value = *value << 1 | pbit;
+                }
+            }
+        }
+        for bits in &mut precision {
+            if *bits > 0 {
+                *bits += 1;
+            }
+        }
+    }
+    for endpoint in &mut endpoints[..ends] {
+        for (value, &bits) in endpoint.iter_mut().zip(&precision) {
+            *value = if bits == 0 {
+                255
+            } else {
+                *value << (8 - bits) | *value >> (2 * bits - 8)
+            };
+        }
+    }
+
+    let mut read_indices = |index_bits: u32| -> [u32; 16] {
+        std::array::from_fn(|i| {
+            let anchor = subset(mode.subsets, partition, i).1;
+            bits.take(if anchor { index_bits - 1 } else { index_bits })
+        })
+    };
+    let primary = read_indices(mode.index_bits);
+    let secondary = if mode.index_bits_2 > 0 {
+        // Only single-subset modes have a second index set; its anchor is
+        // texel 0.
+        read_indices(mode.index_bits_2)
+    } else {
+        primary
+    };
+    let (mut colour_indices, mut colour_bits) = (primary, mode.index_bits);
+    let (mut alpha_indices, mut alpha_bits) = if mode.index_bits_2 > 0 {
+        (secondary, mode.index_bits_2)
+    } else {
+        (primary, mode.index_bits)
+    };
+    if index_selection {
+        std::mem::swap(&mut colour_indices, &mut alpha_indices);
+        std::mem::swap(&mut colour_bits, &mut alpha_bits);
+    }
+
+    std::array::from_fn(|i| {
+        let s = subset(mode.subsets, partition, i).0;
+        let (e0, e1) = (endpoints[2 * s], endpoints[2 * s + 1]);
+        let interpolate = |channel: usize, weight: u32| {
+            (((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6) as u8
+        };
+        let colour_weight = weights(colour_bits)[colour_indices[i] as usize];
+        let alpha_weight = weights(alpha_bits)[alpha_indices[i] as usize];
+        let mut texel = [
+            interpolate(0, colour_weight),
+            interpolate(1, colour_weight),
+            interpolate(2, colour_weight),
+            interpolate(3, alpha_weight),
+        ];
+        match rotation {
+            1 => texel.swap(0, 3),
+            2 => texel.swap(1, 3),
+            3 => texel.swap(2, 3),
+            _ => {}
+        }
+        texel
+    })
+}
+
+// BC6H endpoint fields: channel * 4 + endpoint.
+const R0: u8 = 0;
+const R1: u8 = 1;
+const R2: u8 = 2;
+const R3: u8 = 3;
+const G0: u8 = 4;
+const G1: u8 = 5;
+const G2: u8 = 6;
+const G3: u8 = 7;
+const B0: u8 = 8;
+const B1: u8 = 9;
+const B2: u8 = 10;
+const B3: u8 = 11;
+
+struct Bc6hMode {
+    /// Fields in stream order, in the spec's `field[a:b]` notation: the first
+    /// bit read goes to bit `b`, the rest step towards `a`.
+    layout: &'static [(u8, u8, u8)],
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    transformed: bool,
+    two_regions: bool,
+}
+
+const fn bc6h_mode(
+    layout: &'static [(u8, u8, u8)],
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    transformed: bool,
+    two_regions: bool,
+) -> Bc6hMode {
+    Bc6hMode {
+        layout,
+        endpoint_bits,
+        delta_bits,
+        transformed,
+        two_regions,
+    }
+}
+
+#[rustfmt::skip]
+fn bc6h_mode_for(mode: u32) -> Option<Bc6hMode> {
+    Some(match mode {
+        0 => bc6h_mode(&[(G2, 4, 4), (B2, 4, 4), (B3, 4, 4), (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)], 10, [5, 5, 5], true, true),
+        1 => bc6h_mode(&[(G2, 5, 5), (G3, 4, 4), (G3, 5, 5), (R0, 6, 0), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 6, 0), (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 6, 0), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0)], 7, [6, 6, 6], true, true),
+        2 => bc6h_mode(&[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (R0, 10, 10), (G2, 3, 0), (G1, 3, 0), (G0, 10, 10), (B3, 0, 0), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)], 11, [5, 4, 4], true, true),
+        6 => bc6h_mode(&[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (G0, 10, 10), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 3, 0), (B3, 0, 0), (B3, 2, 2), (R3, 3, 0), (G2, 4, 4), (B3, 3, 3)], 11, [4, 5, 4], true, true),
+        10 => bc6h_mode(&[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (B2, 4, 4), (G2, 3, 0), (G1, 3, 0), (G0, 10, 10), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B0, 10, 10), (B2, 3, 0), (R2, 3, 0), (B3, 1, 1), (B3, 2, 2), (R3, 3, 0), (B3, 4, 4), (B3, 3, 3)], 11, [4, 4, 5], true, true),
+        14 => bc6h_mode(&[(R0, 8, 0), (B2, 4, 4), (G0, 8, 0), (G2, 4, 4), (B0, 8, 0), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)], 9, [5, 5, 5], true, true),
+        18 => bc6h_mode(&[(R0, 7, 0), (G3, 4, 4), (B2, 4, 4), (G0, 7, 0), (B3, 2, 2), (G2, 4, 4), (B0, 7, 0), (B3, 3, 3), (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0)], 8, [6, 5, 5], true, true),
+        22 => bc6h_mode(&[(R0, 7, 0), (B3, 0, 0), (B2, 4, 4), (G0, 7, 0), (G2, 5, 5), (G2, 4, 4), (B0, 7, 0), (G3, 5, 5), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)], 8, [5, 6, 5], true, true),
+        26 => bc6h_mode(&[(R0, 7, 0), (B3, 1, 1), (B2, 4, 4), (G0, 7, 0), (B2, 5, 5), (G2, 4, 4), (B0, 7, 0), (B3, 5, 5), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)], 8, [5, 5, 6], true, true),
+        30 => bc6h_mode(&[(R0, 5, 0), (G3, 4, 4), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 5, 0), (G2, 5, 5), (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 5, 0), (G3, 5, 5), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0)], 6, [6, 6, 6], false, true),
+        3 => bc6h_mode(&[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 9, 0), (G1, 9, 0), (B1, 9, 0)], 10, [10, 10, 10], false, false),
+        7 => bc6h_mode(&[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 8, 0), (R0, 10, 10), (G1, 8, 0), (G0, 10, 10), (B1, 8, 0), (B0, 10, 10)], 11, [9, 9, 9], true, false),
+        11 => bc6h_mode(&[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 7, 0), (R0, 10, 11), (G1, 7, 0), (G0, 10, 11), (B1, 7, 0), (B0, 10, 11)], 12, [8, 8, 8], true, false),
+        15 => bc6h_mode(&[(R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 15), (G1, 3, 0), (G0, 10, 15), (B1, 3, 0), (B0, 10, 15)], 16, [4, 4, 4], true, false),
+        _ => return None,
+    })
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    value << shift >> shift
+}
+
+fn bc6h_unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 || value == 0 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        unquantized * value.signum()
+    }
+}
+
+/// Half-float bit patterns of 16 RGBA texels.
+fn bc6h_block(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    let mut bits = Bits::new(block);
+    let mut mode_bits = bits.take(2);
+    if mode_bits > 1 {
+        mode_bits |= bits.take(3) << 2;
+    }
+    let Some(mode) = bc6h_mode_for(mode_bits) else {
+        // Reserved modes decode to black.
+        return [[0, 0, 0, 0x3c00]; 16];
+    };
+
+    let mut fields = [0i32; 12];
+    for &(field, a, b) in mode.layout {
+        let count = a.abs_diff(b) as u32 + 1;
+        let value = bits.take(count);
+        for k in 0..count {
+            let bit = if a >= b { b as u32 + k } else { b as u32 - k };
+            fields[field as usize] |= ((value >> k & 1) << bit) as i32;
+        }
+    }
+    let partition = if mode.two_regions { bits.take(5) } else { 0 } as usize;
+
+    let endpoints_used = if mode.two_regions { 4 } else { 2 };
+    let mask = (1 << mode.endpoint_bits) - 1;
+    for channel in 0..3 {
+        let base = channel * 4;
+        if signed {
+            fields[base] = sign_extend(fields[base], mode.endpoint_bits);
+        }
+        let first = fields[base];
+        for endpoint in 1..endpoints_used {
+            let value = &mut fields[base + endpoint];
+            if mode.transformed {
+                let delta = sign_extend(*value, mode.delta_bits[channel]);
+                *value = (first + delta) & mask;
+                if signed {
+                    *value = sign_extend(*value, mode.endpoint_bits);
+                }
+            } else if signed {
+                *value = sign_extend(*value, mode.endpoint_bits);
+            }
+        }
+        for endpoint in 0..endpoints_used {
+            fields[base + endpoint] =
+                bc6h_unquantize(fields[base + endpoint], mode.endpoint_bits, signed);
+        }
+    }
+
+    let (subsets, index_bits) = if mode.two_regions { (2, 3) } else { (1, 4) };
+    let indices: [u32; 16] = std::array::from_fn(|i| {
+        let anchor = subset(subsets, partition, i).1;
+        bits.take(if anchor { index_bits - 1 } else { index_bits })
+    });
+
+    std::array::from_fn(|i| {
+        let s = subset(subsets, partition, i).0;
+        let weight = weights(index_bits)[indices[i] as usize] as i32;
+        let channel = |c: usize| {
+            let (e0, e1) = (fields[c * 4 + 2 * s], fields[c * 4 + 2 * s + 1]);
+            let value = ((64 - weight) * e0 + weight * e1 + 32) >> 6;
+            // Scale to the half-float bit pattern.
+            if !signed {
+                ((value * 31) >> 6) as u16
+            } else if value < 0 {
+                0x8000 | ((-value * 31) >> 5) as u16
+            } else {
+                ((value * 31) >> 5) as u16
+            }
+        };
+        [channel(0), channel(1), channel(2), 0x3c00]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs `(value, bits)` fields LSB first, the order blocks are read in.
+    fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+        let (mut block, mut offset) = (0u128, 0);
+        for &(value, bits) in fields {
+            assert!(value < 1 << bits, "{value} doesn't fit {bits} bits");
+            block |= (value as u128) << offset;
+            offset += bits;
+        }
+        assert_eq!(offset, 128);
+        block.to_le_bytes().to_vec()
+    }
+
+    fn texels(format: F, block: &[u8]) -> Vec<[u8; 4]> {
+        decode(format, 4, 4, block)
+            .chunks_exact(4)
+            .map(|t| t.try_into().unwrap())
+            .collect()
+    }
+
+    fn halves(block: &[u8], signed: bool) -> Vec<[u16; 4]> {
+        let format = if signed {
+            F::Bc6hRgbFloat
+        } else {
+            F::Bc6hRgbUfloat
+        };
+        decode(format, 4, 4, block)
+            .chunks_exact(8)
+            .map(|t| std::array::from_fn(|c| u16::from_le_bytes([t[2 * c], t[2 * c + 1]])))
+            .collect()
+    }
+
+    #[test]
+    fn bc1_solid_colour() {
+        let block = [0x00, 0xf8, 0x00, 0x00, 0, 0, 0, 0];
+        assert_eq!(texels(F::Bc1RgbaUnorm, &block), [[255, 0, 0, 255]; 16]);
+    }
+
+    #[test]
+    fn bc1_three_colour_mode_has_transparent_black() {
+        // c0 <= c1: blue, red, their average and transparent black.
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0b11_10_01_00, 0, 0, 0];
+        let texels = texels(F::Bc1RgbaUnorm, &block);
+        assert_eq!(
+            texels[..4],
+            [
+                [0, 0, 255, 255],
+                [255, 0, 0, 255],
+                [127, 0, 127, 255],
+                [0, 0, 0, 0]
+            ]
+        );
+        assert_eq!(texels[4], [0, 0, 255, 255]);
+    }
+
+    /// Indices 0, 1, 2 and 7 in the first four texels, then 0.
+    const BC4_EIGHT_VALUES: [u8; 8] = [200, 60, 0x88, 0x0e, 0, 0, 0, 0];
+    /// Indices 2, 5, 6 and 7 in the first four texels, then 0.
+    const BC4_SIX_VALUES: [u8; 8] = [50, 150, 0xaa, 0x0f, 0, 0, 0, 0];
+
+    #[test]
+    fn bc4_eight_value_ordering() {
+        let red: Vec<_> = texels(F::Bc4RUnorm, &BC4_EIGHT_VALUES)
+            .iter()
+            .map(|t| t[0])
+            .collect();
+        assert_eq!(red[..5], [200, 60, 180, 80, 200]);
+    }
+
+    #[test]
+    fn bc4_six_value_ordering_has_explicit_extremes() {
+        let red: Vec<_> = texels(F::Bc4RUnorm, &BC4_SIX_VALUES)
+            .iter()
+            .map(|t| t[0])
+            .collect();
+        assert_eq!(red[..5], [70, 130, 0, 255, 50]);
+    }
+
+    #[test]
+    fn bc5_decodes_both_channels() {
+        let block = [BC4_EIGHT_VALUES, BC4_SIX_VALUES].concat();
+        let texels = texels(F::Bc5RgUnorm, &block);
+        assert_eq!(
+            texels[..4],
+            [
+                [200, 70, 0, 255],
+                [60, 130, 0, 255],
+                [180, 0, 0, 255],
+                [80, 255, 0, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        let mut fields = vec![(1 << 6, 7)];
+        // Black to white in every channel, p-bits 0 and 1.
+        fields.extend([(0, 7), (127, 7)].repeat(4));
+        fields.extend([(0, 1), (1, 1)]);
+        // Texel 0 is the anchor, with one index bit fewer.
+        fields.extend([(0, 3), (15, 4), (8, 4)]);
+        fields.extend([(0, 4); 13]);
+        let texels = texels(F::Bc7RgbaUnorm, &pack(&fields));
+        assert_eq!(texels[..4], [[0; 4], [255; 4], [135; 4], [0; 4]]);
+    }
+
+    #[test]
+    fn bc7_mode_1_partition() {
+        // Partition 13 puts the bottom two rows in subset 1, anchored at
+        // texel 15.
+        let mut fields = vec![(0b10, 2), (13, 6)];
+        // Subset 0 red at both ends; subset 1 from blue to black.
+        fields.extend([(63, 6), (63, 6), (0, 6), (0, 6)]);
+        fields.extend([(0, 6); 4]);
+        fields.extend([(0, 6), (0, 6), (63, 6), (0, 6)]);
+        // Shared p-bits per subset.
+        fields.extend([(0, 1), (1, 1)]);
+        fields.push((0, 2));
+        fields.extend([(0, 3); 7]);
+        fields.push((7, 3));
+        fields.extend([(0, 3); 6]);
+        fields.push((3, 2));
+        let texels = texels(F::Bc7RgbaUnorm, &pack(&fields));
+        assert_eq!(texels[..8], [[253, 0, 0, 255]; 8]);
+        assert_eq!(texels[8], [2, 2, 2, 255]);
+        assert_eq!(texels[9..15], [[2, 2, 255, 255]; 6]);
+        assert_eq!(texels[15], [2, 2, 148, 255]);
+    }
+
+    /// Mode 11 (`00011`): one region with untransformed 10-bit endpoints.
+    fn bc6h_mode_11(e0: u32, e1: u32) -> Vec<u8> {
+        let mut fields = vec![(0b00011, 5)];
+        fields.extend([(e0, 10); 3]);
+        fields.extend([(e1, 10); 3]);
+        fields.extend([(0, 3), (15, 4), (8, 4)]);
+        fields.extend([(0, 4); 13]);
+        pack(&fields)
+    }
+
+    #[test]
+    fn bc6h_unsigned_mode_11() {
+        let texels = halves(&bc6h_mode_11(0, 1023), false);
+        assert_eq!(texels[0], [0, 0, 0, 0x3c00]);
+        let max = half::f16::MAX.to_bits();
+        assert_eq!(texels[1], [max, max, max, 0x3c00]);
+        assert_eq!(texels[2], [0x41df, 0x41df, 0x41df, 0x3c00]);
+    }
+
+    #[test]
+    fn bc6h_signed_mode_11() {
+        // -511 and 511 in 10-bit two's complement.
+        let texels = halves(&bc6h_mode_11(0x201, 0x1ff), true);
+        let max = half::f16::MAX.to_bits();
+        assert_eq!(
+            texels[0],
+            [0x8000 | max, 0x8000 | max, 0x8000 | max, 0x3c00]
+        );
+        assert_eq!(texels[1], [max, max, max, 0x3c00]);
+        assert_eq!(texels[2], [0x07c0, 0x07c0, 0x07c0, 0x3c00]);
+    }
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        let red = [0x00, 0xf8, 0x00, 0x00, 0, 0, 0, 0];
+        let green = [0xe0, 0x07, 0x00, 0x00, 0, 0, 0, 0];
+        let data = decode(F::Bc1RgbaUnorm, 5, 3, &[red, green].concat());
+        assert_eq!(data.len(), 5 * 3 * 4);
+        for (i, texel) in data.chunks_exact(4).enumerate() {
+            let expected = if i % 5 < 4 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 255, 0, 255]
+            };
+            assert_eq!(texel, expected, "texel {i}");
+        }
+    }
+}
@@ -13,7 +13,950 @@ fn vs_main(@builtin(vertex_index) vertex_idx: u32) -> VertexOutput {
 @group(0) @binding(0) var tex: texture_2d<f32>;
 @group(0) @binding(1) var tex_sampler: sampler;
 
+// Where one tile's core sits in its texture, which has a texel of its
+// neighbours around it so filtering across tile edges matches.
+struct Tile {
+    offset: vec2<f32>,
+    scale: vec2<f32>,
+    orientation: u32,
+};
+
+@group(0) @binding(14) var<uniform> tile: Tile;
+
+@vertex
+fn vs_main_tile(@builtin(vertex_index) vertex_idx: u32) -> VertexOutput {
+    let uv = vec2(f32(vertex_idx & 2u), f32((vertex_idx << 1u) & 2u));
+    let pos = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return VertexOutput(pos, tile.offset + tile.scale * orient(tile.orientation, uv));
+}
+
+struct Slice {
+    w: f32,
+};
+
+@group(0) @binding(2) var tex_3d: texture_3d<f32>;
+@group(0) @binding(3) var<uniform> slice: Slice;
+
+struct Resolve {
+    samples: u32,
+};
+
+@group(0) @binding(4) var tex_ms: texture_multisampled_2d<f32>;
+// Passed in, as textureNumSamples has no GLSL ES equivalent.
+@group(0) @binding(15) var<uniform> resolve: Resolve;
+
+fn load_coords(uv: vec2<f32>, dims: vec2<u32>) -> vec2<i32> {
+    let dims = vec2<i32>(dims);
+    return clamp(vec2<i32>(uv * vec2<f32>(dims)), vec2(0), dims - 1);
+}
+
+struct DepthParams {
+    near: f32,
+    far: f32,
+    reverse_z: u32,
+    ramp: u32,
+};
+
+// Bound as unfilterable float, as GLSL can't textureLoad depth textures.
+@group(0) @binding(5) var tex_depth: texture_2d<f32>;
+@group(0) @binding(6) var<uniform> depth_params: DepthParams;
+
+// https://gist.github.com/mikhailov-work/0d177465a8151eb6ede1768d51d476c7
+fn turbo(t: f32) -> vec3<f32> {
+    let r4 = vec4(0.13572138, 4.61539260, -42.66032258, 132.13108234);
+    let g4 = vec4(0.09140261, 2.19418839, 4.84296658, -14.18503333);
+    let b4 = vec4(0.10667330, 12.64194608, -60.58204836, 110.36276771);
+    let r2 = vec2(-152.94239396, 59.28637943);
+    let g2 = vec2(4.27729857, 2.82956604);
+    let b2 = vec2(-89.90310912, 27.34824973);
+    let v4 = vec4(1., t, t * t, t * t * t);
+    let v2 = v4.zw * v4.z;
+    return vec3(
+        dot(v4, r4) + dot(v2, r2),
+        dot(v4, g4) + dot(v2, g2),
+        dot(v4, b4) + dot(v2, b2),
+    );
+}
+
+@group(0) @binding(7) var tex_uint: texture_2d<u32>;
+@group(0) @binding(8) var tex_sint: texture_2d<i32>;
+
+struct Integer {
+    channels: u32,
+};
+
+@group(0) @binding(16) var<uniform> integer: Integer;
+
+// https://www.pcg-random.org/
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn hash_colour(loaded: vec4<u32>) -> vec4<f32> {
+    // Loads fill missing channels with (0, 0, 1), which mustn't count.
+    let n = integer.channels;
+    let id = select(vec4(0u), loaded, vec4(n > 0u, n > 1u, n > 2u, n > 3u));
+    if all(id == vec4(0u)) {
+        return vec4(0., 0., 0., 1.);
+    }
+    let h = pcg(id.x ^ pcg(id.y ^ pcg(id.z ^ pcg(id.w))));
+    let rgb = vec3(h & 0xffu, (h >> 8u) & 0xffu, (h >> 16u) & 0xffu);
+    return vec4(vec3<f32>(rgb) / 255., 1.);
+}
+
+struct Options {
+    swizzle: vec4<u32>,
+    exposure: f32,
+    tonemap: u32,
+    white: f32,
+    source_space: u32,
+    target_space: u32,
+    gamut_mapping: u32,
+    highlight_gamut: u32,
+    lut_space: u32,
+    lut_interpolation: u32,
+    dither: u32,
+    dither_frame: u32,
+    dither_step: f32,
+    dither_srgb: u32,
+    orientation: u32,
+    linear_peak: f32,
+    color_matrix: mat4x4<f32>,
+    color_offset: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> options: Options;
+// 64x64 blue-noise ranks, four bytes to a u32. Only bound with real data for
+// blue-noise dithering.
+@group(1) @binding(1) var<uniform> blue_noise: array<vec4<u32>, 256>;
+
+fn apply_swizzle(c: vec4<f32>) -> vec4<f32> {
+    var channels = array<f32, 6>(c.r, c.g, c.b, c.a, 0., 1.);
+    let s = options.swizzle;
+    return vec4(channels[s.x], channels[s.y], channels[s.z], channels[s.w]);
+}
+
+fn srgb_to_linear(rgb: vec3<f32>) -> vec3<f32> {
+    let rgb = clamp(rgb, vec3(0.), vec3(1.));
+    return select(
+        pow((rgb + 0.055) * (1.0 / 1.055), vec3(2.4)),
+        rgb * (1.0 / 12.92),
+        rgb <= vec3(0.04045)
+    );
+}
+
+fn linear_to_srgb(rgb: vec3<f32>) -> vec3<f32> {
+    let rgb = clamp(rgb, vec3(0.), vec3(1.));
+    return select(
+        1.055 * pow(rgb, vec3(1.0 / 2.4)) - 0.055,
+        rgb * 12.92,
+        rgb <= vec3(0.0031308)
+    );
+}
+
+// ST 2084, normalised so that 1.0 is the 203 nit reference white of BT.2408.
+const PQ_M1 = 0.1593017578125;
+const PQ_M2 = 78.84375;
+const PQ_C1 = 0.8359375;
+const PQ_C2 = 18.8515625;
+const PQ_C3 = 18.6875;
+const PQ_REFERENCE_WHITE = 203.;
+
+fn pq_to_linear(rgb: vec3<f32>) -> vec3<f32> {
+    let e = pow(clamp(rgb, vec3(0.), vec3(1.)), vec3(1. / PQ_M2));
+    let y = pow(max(e - PQ_C1, vec3(0.)) / (PQ_C2 - PQ_C3 * e), vec3(1. / PQ_M1));
+    return y * (10000. / PQ_REFERENCE_WHITE);
+}
+
+fn linear_to_pq(rgb: vec3<f32>) -> vec3<f32> {
+    let y = pow(clamp(rgb * (PQ_REFERENCE_WHITE / 10000.), vec3(0.), vec3(1.)), vec3(PQ_M1));
+    return pow((PQ_C1 + PQ_C2 * y) / (1. + PQ_C3 * y), vec3(PQ_M2));
+}
+
+// BT.2100 HLG OETF without the OOTF. Scene light at 75% signal maps to 1.0.
+const HLG_A = 0.17883277;
+const HLG_B = 0.28466892;
+const HLG_C = 0.55991073;
+const HLG_REFERENCE_WHITE = 0.26496256;
+
+fn hlg_to_linear(rgb: vec3<f32>) -> vec3<f32> {
+    let e = clamp(rgb, vec3(0.), vec3(1.));
+    let scene = select(
+        (exp((e - HLG_C) / HLG_A) + HLG_B) / 12.,
+        e * e / 3.,
+        e <= vec3(0.5)
+    );
+    return scene / HLG_REFERENCE_WHITE;
+}
+
+fn linear_to_hlg(rgb: vec3<f32>) -> vec3<f32> {
+    let scene = clamp(rgb * HLG_REFERENCE_WHITE, vec3(0.), vec3(1.));
+    return select(
+        HLG_A * log(max(12. * scene - HLG_B, vec3(1e-6))) + HLG_C,
+        sqrt(3. * scene),
+        scene <= vec3(1. / 12.)
+    );
+}
+
+// BT.1886 display gamma used for Rec.2020 SDR content.
+fn bt1886_to_linear(rgb: vec3<f32>) -> vec3<f32> {
+    return pow(clamp(rgb, vec3(0.), vec3(1.)), vec3(2.4));
+}
+
+fn linear_to_bt1886(rgb: vec3<f32>) -> vec3<f32> {
+    return pow(clamp(rgb, vec3(0.), vec3(1.)), vec3(1. / 2.4));
+}
+
+// Primaries conversions, all D65. Written row-major, hence the transposes.
+fn p3_to_rec709() -> mat3x3<f32> {
+    return transpose(mat3x3(
+        vec3(1.2249401, -0.2249404, 0.0),
+        vec3(-0.0420569, 1.0420571, 0.0),
+        vec3(-0.0196376, -0.0786361, 1.0982735),
+    ));
+}
+
+fn rec709_to_p3() -> mat3x3<f32> {
+    return transpose(mat3x3(
+        vec3(0.8224621, 0.1775380, 0.0),
+        vec3(0.0331941, 0.9668058, 0.0),
+        vec3(0.0170827, 0.0723974, 0.9105199),
+    ));
+}
+
+// Adobe RGB shares sRGB's red and blue primaries.
+fn adobe_to_rec709() -> mat3x3<f32> {
+    return transpose(mat3x3(
+        vec3(1.3983557, -0.3983557, 0.0),
+        vec3(0.0, 1.0, 0.0),
+        vec3(0.0, -0.0429383, 1.0429383),
+    ));
+}
+
+fn rec709_to_adobe() -> mat3x3<f32> {
+    return transpose(mat3x3(
+        vec3(0.7151386, 0.2848614, 0.0),
+        vec3(0.0, 1.0, 0.0),
+        vec3(0.0, 0.0411705, 0.9588295),
+    ));
+}
+
+// Adobe RGB (1998) is a pure 563/256 power law.
+const ADOBE_GAMMA: f32 = 2.19921875;
+
+fn rec2020_to_rec709() -> mat3x3<f32> {
+    return transpose(mat3x3(
+        vec3(1.6604910, -0.5876411, -0.0728499),
+        vec3(-0.1245505, 1.1328999, -0.0083494),
+        vec3(-0.0181508, -0.1005789, 1.1187297),
+    ));
+}
+
+fn rec709_to_rec2020() -> mat3x3<f32> {
+    return transpose(mat3x3(
+        vec3(0.6274040, 0.3292820, 0.0433136),
+        vec3(0.0690970, 0.9195400, 0.0113612),
+        vec3(0.0163916, 0.0880132, 0.8955950),
+    ));
+}
+
+// Indices follow `ColourSpace`. Decodes into linear Rec.709 where 1.0 is SDR white.
+fn decode(space: u32, c: vec4<f32>) -> vec4<f32> {
+    switch space {
+        case 1u: {
+            return vec4(c.rgb * exp2(c.a * 255. - 128.), 1.);
+        }
+        case 2u: {
+            return vec4(srgb_to_linear(c.rgb), c.a);
+        }
+        case 3u: {
+            return vec4(p3_to_rec709() * srgb_to_linear(c.rgb), c.a);
+        }
+        case 4u: {
+            return vec4(rec2020_to_rec709() * bt1886_to_linear(c.rgb), c.a);
+        }
+        case 5u: {
+            return vec4(rec2020_to_rec709() * pq_to_linear(c.rgb), c.a);
+        }
+        case 6u: {
+            return vec4(rec2020_to_rec709() * hlg_to_linear(c.rgb), c.a);
+        }
+        case 8u: {
+            return vec4(adobe_to_rec709() * pow(max(c.rgb, vec3(0.)), vec3(ADOBE_GAMMA)), c.a);
+        }
+        default: {
+            return c;
+        }
+    }
+}
+
+fn to_primaries(space: u32, rgb: vec3<f32>) -> vec3<f32> {
+    switch space {
+        case 3u: {
+            return rec709_to_p3() * rgb;
+        }
+        case 4u, 5u, 6u: {
+            return rec709_to_rec2020() * rgb;
+        }
+        case 8u: {
+            return rec709_to_adobe() * rgb;
+        }
+        default: {
+            return rgb;
+        }
+    }
+}
+
+// Largest value RGBE can hold, (255 / 255) * 2^(255 - 128).
+const RGBE_MAX: f32 = 1.7014118e38;
+
+// Largest value the target transfer can represent, relative to SDR white.
+fn target_peak() -> f32 {
+    switch options.target_space {
+        case 0u: {
+            return options.linear_peak;
+        }
+        case 1u: {
+            return RGBE_MAX;
+        }
+        case 5u: {
+            return 10000. / PQ_REFERENCE_WHITE;
+        }
+        case 6u: {
+            return 1. / HLG_REFERENCE_WHITE;
+        }
+        default: {
+            return 1.;
+        }
+    }
+}
+
+fn encode_transfer(space: u32, rgb: vec3<f32>) -> vec3<f32> {
+    switch space {
+        case 2u, 3u: {
+            return linear_to_srgb(rgb);
+        }
+        case 4u: {
+            return linear_to_bt1886(rgb);
+        }
+        case 5u: {
+            return linear_to_pq(rgb);
+        }
+        case 6u: {
+            return linear_to_hlg(rgb);
+        }
+        case 8u: {
+            return pow(max(rgb, vec3(0.)), vec3(1. / ADOBE_GAMMA));
+        }
+        default: {
+            return rgb;
+        }
+    }
+}
+
+// Inverse of the RGBE branch of `decode`: the exponent is the smallest that
+// keeps every channel at or below 1, so unorm rounding is the only loss.
+fn linear_to_rgbe(rgb: vec3<f32>) -> vec4<f32> {
+    let c = clamp(rgb, vec3(0.), vec3(RGBE_MAX));
+    let peak = max(c.r, max(c.g, c.b));
+    if peak < 1e-32 {
+        return vec4(0.);
+    }
+    let e = clamp(ceil(log2(peak)), -128., 127.);
+    return vec4(c * exp2(-e), (e + 128.) / 255.);
+}
+
+// EXT_texture_shared_exponent packing: 9-bit mantissas, exponent bias 15.
+fn linear_to_rgb9e5(rgb: vec3<f32>) -> u32 {
+    let c = clamp(rgb, vec3(0.), vec3(65408.));
+    let peak = max(c.r, max(c.g, c.b));
+    var e = max(-16., floor(log2(peak))) + 16.;
+    if floor(peak * exp2(24. - e) + 0.5) >= 512. {
+        e += 1.;
+    }
+    let m = vec3<u32>(floor(c * exp2(24. - e) + 0.5));
+    return m.r | (m.g << 9u) | (m.b << 18u) | (u32(e) << 27u);
+}
+
+fn encode_target(c: vec4<f32>) -> vec4<f32> {
+    if options.target_space == 1u {
+        return linear_to_rgbe(c.rgb);
+    }
+    return vec4(encode_transfer(options.target_space, c.rgb), c.a);
+}
+
+fn luminance(rgb: vec3<f32>) -> f32 {
+    return dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// https://bottosson.github.io/posts/oklab/
+fn linear_to_oklab(rgb: vec3<f32>) -> vec3<f32> {
+    let lms = transpose(mat3x3(
+        vec3(0.4122214708, 0.5363325363, 0.0514459929),
+        vec3(0.2119034982, 0.6806995451, 0.1073969566),
+        vec3(0.0883024619, 0.2817188376, 0.6299787005),
+    )) * rgb;
+    let lms_ = sign(lms) * pow(abs(lms), vec3(1. / 3.));
+    return transpose(mat3x3(
+        vec3(0.2104542553, 0.7936177850, -0.0040720468),
+        vec3(1.9779984951, -2.4285922050, 0.4505937099),
+        vec3(0.0259040371, 0.7827717662, -0.8086757660),
+    )) * lms_;
+}
+
+fn oklab_to_linear(lab: vec3<f32>) -> vec3<f32> {
+    let lms_ = transpose(mat3x3(
+        vec3(1., 0.3963377774, 0.2158037573),
+        vec3(1., -0.1055613458, -0.0638541728),
+        vec3(1., -0.0894841775, -1.2914855480),
+    )) * lab;
+    return transpose(mat3x3(
+        vec3(4.0767416621, -3.3077115913, 0.2309699292),
+        vec3(-1.2684380046, 2.6097574011, -0.3413193965),
+        vec3(-0.0041960863, -0.7034186147, 1.7076147010),
+    )) * (lms_ * lms_ * lms_);
+}
+
+fn in_target_gamut(rgb: vec3<f32>) -> bool {
+    let target_rgb = to_primaries(options.target_space, rgb);
+    let eps = 1e-4;
+    return all(target_rgb >= vec3(-eps)) && all(target_rgb <= vec3(target_peak() + eps));
+}
+
+// Pulls `rgb` towards grey of the same luminance until it fits. Grey maps to
+// grey under every D65 primaries conversion, so this works in Rec.709.
+fn gamut_desaturate(rgb: vec3<f32>) -> vec3<f32> {
+    let peak = target_peak();
+    let y = clamp(luminance(rgb), 0., peak);
+    let target_rgb = to_primaries(options.target_space, rgb);
+    var t = 1.;
+    for (var i = 0; i < 3; i++) {
+        let v = target_rgb[i];
+        if v < 0. {
+            t = min(t, y / (y - v));
+        } else if v > peak {
+            t = min(t, (peak - y) / (v - y));
+        }
+    }
+    return mix(vec3(y), rgb, t);
+}
+
+// Reduces OkLCh chroma at constant lightness and hue.
+fn gamut_perceptual(rgb: vec3<f32>) -> vec3<f32> {
+    if in_target_gamut(rgb) {
+        return rgb;
+    }
+    let lab = linear_to_oklab(rgb);
+    let l = clamp(lab.x, 0., linear_to_oklab(vec3(target_peak())).x);
+    var lo = 0.;
+    var hi = 1.;
+    for (var i = 0; i < 16; i++) {
+        let mid = 0.5 * (lo + hi);
+        if in_target_gamut(oklab_to_linear(vec3(l, lab.yz * mid))) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    return oklab_to_linear(vec3(l, lab.yz * lo));
+}
+
+// Indices follow `GamutMapping`. Returns colour in target primaries.
+fn gamut_map(rgb: vec3<f32>) -> vec3<f32> {
+    // scRGB and RGBE are unbounded.
+    if options.target_space == 1u || options.target_space == 7u {
+        return rgb;
+    }
+    var mapped = rgb;
+    switch options.gamut_mapping {
+        case 1u: {
+            mapped = gamut_desaturate(rgb);
+        }
+        case 2u: {
+            mapped = gamut_perceptual(rgb);
+        }
+        default: {}
+    }
+    return clamp(to_primaries(options.target_space, mapped), vec3(0.), vec3(target_peak()));
+}
+
+fn highlight_out_of_gamut(rgb: vec3<f32>, mapped: vec3<f32>, pos: vec2<f32>) -> vec3<f32> {
+    if options.highlight_gamut == 0u || in_target_gamut(rgb) {
+        return mapped;
+    }
+    let stripe = (i32(pos.x + pos.y) / 4) % 2 == 0;
+    return select(mapped, to_primaries(options.target_space, vec3(1., 0., 1.)), stripe);
+}
+
+fn tonemap_reinhard_extended(c: vec3<f32>, white: f32) -> vec3<f32> {
+    return c * (1. + c / (white * white)) / (1. + c);
+}
+
+// https://github.com/TheRealMJP/BakingLab/blob/master/BakingLab/ACES.hlsl
+fn tonemap_aces_fitted(c: vec3<f32>) -> vec3<f32> {
+    let aces_input = mat3x3(
+        vec3(0.59719, 0.07600, 0.02840),
+        vec3(0.35458, 0.90834, 0.13383),
+        vec3(0.04823, 0.01566, 0.83777),
+    );
+    let aces_output = mat3x3(
+        vec3(1.60475, -0.10208, -0.00327),
+        vec3(-0.53108, 1.10813, -0.07276),
+        vec3(-0.07367, -0.00605, 1.07602),
+    );
+    let v = aces_input * c;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return aces_output * (a / b);
+}
+
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+        + 0.1191 * x - 0.00232;
+}
+
+fn tonemap_agx(c: vec3<f32>) -> vec3<f32> {
+    let agx_input = mat3x3(
+        vec3(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let agx_output = mat3x3(
+        vec3(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    let ev = clamp(log2(agx_input * c), vec3(min_ev), vec3(max_ev));
+    let v = agx_contrast((ev - min_ev) / (max_ev - min_ev));
+    return pow(max(agx_output * v, vec3(0.)), vec3(2.2));
+}
+
+fn hable_partial(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+fn tonemap_hable(c: vec3<f32>) -> vec3<f32> {
+    let exposure_bias = 2.;
+    let white = vec3(11.2);
+    return hable_partial(c * exposure_bias) / hable_partial(white);
+}
+
+// Indices follow `Tonemap::index`.
+fn tonemap(rgb: vec3<f32>) -> vec3<f32> {
+    let c = max(rgb, vec3(0.));
+    switch options.tonemap {
+        case 1u: {
+            return c / (1. + c);
+        }
+        case 2u: {
+            return tonemap_reinhard_extended(c, options.white);
+        }
+        case 3u: {
+            return tonemap_aces_fitted(c);
+        }
+        case 4u: {
+            return tonemap_agx(c);
+        }
+        case 5u: {
+            return tonemap_hable(c);
+        }
+        default: {
+            return c;
+        }
+    }
+}
+
 @fragment
 fn fs_main(vout: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(tex, tex_sampler, vout.tex_coords);
 }
+
+struct LutDomain {
+    min: vec4<f32>,
+    max: vec4<f32>,
+};
+
+@group(2) @binding(0) var lut: texture_3d<f32>;
+@group(2) @binding(1) var lut_sampler: sampler;
+@group(2) @binding(2) var<uniform> lut_domain: LutDomain;
+
+fn lut_trilinear(uvw: vec3<f32>) -> vec3<f32> {
+    let n = f32(textureDimensions(lut).x);
+    let coords = (uvw * (n - 1.) + 0.5) / n;
+    return textureSampleLevel(lut, lut_sampler, coords, 0.).rgb;
+}
+
+fn lut_load(coords: vec3<i32>) -> vec3<f32> {
+    return textureLoad(lut, coords, 0).rgb;
+}
+
+// https://docs.acescentral.com/specifications/clf/#tetrahedral-interpolation
+fn lut_tetrahedral(uvw: vec3<f32>) -> vec3<f32> {
+    let n = i32(textureDimensions(lut).x);
+    let p = uvw * f32(n - 1);
+    let base = min(vec3<i32>(floor(p)), vec3(n - 2));
+    let f = p - vec3<f32>(base);
+    let c000 = lut_load(base);
+    let c111 = lut_load(base + vec3(1, 1, 1));
+    if f.r > f.g {
+        if f.g > f.b {
+            let c100 = lut_load(base + vec3(1, 0, 0));
+            let c110 = lut_load(base + vec3(1, 1, 0));
+            return (1. - f.r) * c000 + (f.r - f.g) * c100 + (f.g - f.b) * c110 + f.b * c111;
+        } else if f.r > f.b {
+            let c100 = lut_load(base + vec3(1, 0, 0));
+            let c101 = lut_load(base + vec3(1, 0, 1));
+            return (1. - f.r) * c000 + (f.r - f.b) * c100 + (f.b - f.g) * c101 + f.g * c111;
+        } else {
+            let c001 = lut_load(base + vec3(0, 0, 1));
+            let c101 = lut_load(base + vec3(1, 0, 1));
+            return (1. - f.b) * c000 + (f.b - f.r) * c001 + (f.r - f.g) * c101 + f.g * c111;
+        }
+    } else {
+        if f.b > f.g {
+            let c001 = lut_load(base + vec3(0, 0, 1));
+            let c011 = lut_load(base + vec3(0, 1, 1));
+            return (1. - f.b) * c000 + (f.b - f.g) * c001 + (f.g - f.r) * c011 + f.r * c111;
+        } else if f.b > f.r {
+            let c010 = lut_load(base + vec3(0, 1, 0));
+            let c011 = lut_load(base + vec3(0, 1, 1));
+            return (1. - f.g) * c000 + (f.g - f.b) * c010 + (f.b - f.r) * c011 + f.r * c111;
+        } else {
+            let c010 = lut_load(base + vec3(0, 1, 0));
+            let c110 = lut_load(base + vec3(1, 1, 0));
+            return (1. - f.g) * c000 + (f.g - f.r) * c010 + (f.r - f.b) * c110 + f.b * c111;
+        }
+    }
+}
+
+fn apply_lut(rgb: vec3<f32>) -> vec3<f32> {
+    let encoded = encode_transfer(options.lut_space, to_primaries(options.lut_space, rgb));
+    let uvw = saturate((encoded - lut_domain.min.xyz) / (lut_domain.max.xyz - lut_domain.min.xyz));
+    var graded: vec3<f32>;
+    if options.lut_interpolation == 1u {
+        graded = lut_tetrahedral(uvw);
+    } else {
+        graded = lut_trilinear(uvw);
+    }
+    return decode(options.lut_space, vec4(graded, 1.)).rgb;
+}
+
+// Source decode, exposure, tone mapping and colour adjustment, in linear Rec.709.
+fn grade_input(texel: vec4<f32>) -> vec4<f32> {
+    let c = decode(options.source_space, apply_swizzle(texel));
+    let tonemapped = vec4(tonemap(c.rgb * options.exposure), c.a);
+    return options.color_matrix * tonemapped + options.color_offset;
+}
+
+fn bayer(pos: vec2<u32>, bits: u32) -> f32 {
+    var v = 0u;
+    for (var i = 0u; i < bits; i++) {
+        let x = (pos.x >> i) & 1u;
+        let y = (pos.y >> i) & 1u;
+        v |= (((x ^ y) << 1u) | y) << (2u * (bits - 1u - i));
+    }
+    return (f32(v) + 0.5) / f32(1u << (2u * bits));
+}
+
+fn uniform_hash(v: u32) -> f32 {
+    return f32(pcg(v) >> 8u) / 16777216.;
+}
+
+// Indices follow `Dither`. Noise in quantisation steps, zero mean.
+fn dither_noise(pos: vec2<u32>) -> f32 {
+    // Golden ratio offsets decorrelate consecutive frames of the ordered patterns.
+    let shift = fract(f32(options.dither_frame) * 0.61803398875);
+    switch options.dither {
+        case 1u: {
+            return fract(bayer(pos, 2u) + shift) - 0.5;
+        }
+        case 2u: {
+            return fract(bayer(pos, 3u) + shift) - 0.5;
+        }
+        case 3u: {
+            let p = pos % 64u;
+            let i = p.y * 64u + p.x;
+            let word = blue_noise[i / 16u][(i / 4u) % 4u];
+            let noise = f32(extractBits(word, (i % 4u) * 8u, 8u)) / 255.;
+            return fract(noise + shift) - 0.5;
+        }
+        case 4u: {
+            let seed = pcg(pos.x ^ pcg(pos.y ^ pcg(options.dither_frame)));
+            return uniform_hash(seed) + uniform_hash(seed + 1u) - 1.;
+        }
+        default: {
+            return 0.;
+        }
+    }
+}
+
+fn apply_dither(c: vec4<f32>, pos: vec2<f32>) -> vec4<f32> {
+    if options.dither == 0u || options.dither_step == 0. {
+        return c;
+    }
+    let noise = dither_noise(vec2<u32>(pos)) * options.dither_step;
+    if options.dither_srgb != 0u {
+        // The target encodes to sRGB after us, so dither in encoded space.
+        let encoded = linear_to_srgb(c.rgb) + noise;
+        return vec4(srgb_to_linear(encoded), c.a);
+    }
+    return vec4(c.rgb + noise, c.a);
+}
+
+// Gamut mapping, encoding into the target space and dithering.
+fn grade_output(c: vec4<f32>, pos: vec2<f32>) -> vec4<f32> {
+    let mapped = highlight_out_of_gamut(c.rgb, gamut_map(c.rgb), pos);
+    return apply_dither(encode_target(vec4(mapped, c.a)), pos);
+}
+
+// Maps displayed coordinates to stored ones for an EXIF orientation, minus
+// one: mirrors, then 180, transpose and the two quarter turns.
+fn orient(orientation: u32, uv: vec2<f32>) -> vec2<f32> {
+    switch orientation {
+        case 1u: {
+            return vec2(1. - uv.x, uv.y);
+        }
+        case 2u: {
+            return 1. - uv;
+        }
+        case 3u: {
+            return vec2(uv.x, 1. - uv.y);
+        }
+        case 4u: {
+            return uv.yx;
+        }
+        case 5u: {
+            return vec2(uv.y, 1. - uv.x);
+        }
+        case 6u: {
+            return 1. - uv.yx;
+        }
+        case 7u: {
+            return vec2(1. - uv.y, uv.x);
+        }
+        default: {
+            return uv;
+        }
+    }
+}
+
+@fragment
+fn fs_main_options(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(tex, tex_sampler, orient(options.orientation, vout.tex_coords));
+    return grade_output(grade_input(texel), vout.position.xy);
+}
+
+@fragment
+fn fs_main_options_lut(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(tex, tex_sampler, orient(options.orientation, vout.tex_coords));
+    let c = grade_input(texel);
+    return grade_output(vec4(apply_lut(c.rgb), c.a), vout.position.xy);
+}
+
+@fragment
+fn fs_main_3d(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(tex_3d, tex_sampler, vec3(vout.tex_coords, slice.w));
+}
+
+@fragment
+fn fs_main_resolve_box(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = load_coords(vout.tex_coords, textureDimensions(tex_ms));
+    let samples = resolve.samples;
+    var sum = vec4(0.);
+    for (var i = 0u; i < samples; i++) {
+        sum += textureLoad(tex_ms, coords, i32(i));
+    }
+    return sum / f32(samples);
+}
+
+@fragment
+fn fs_main_resolve_tonemapped(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = load_coords(vout.tex_coords, textureDimensions(tex_ms));
+    let samples = resolve.samples;
+    var sum = vec4(0.);
+    for (var i = 0u; i < samples; i++) {
+        let c = textureLoad(tex_ms, coords, i32(i));
+        sum += vec4(c.rgb / (1. + max(c.r, max(c.g, c.b))), c.a);
+    }
+    let avg = sum / f32(samples);
+    return vec4(avg.rgb / max(1. - max(avg.r, max(avg.g, avg.b)), 1e-4), avg.a);
+}
+
+@fragment
+fn fs_main_depth(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = load_coords(vout.tex_coords, textureDimensions(tex_depth));
+    let d = textureLoad(tex_depth, coords, 0).r;
+    let near = depth_params.near;
+    let far = depth_params.far;
+    var z: f32;
+    if depth_params.reverse_z != 0u {
+        z = near * far / (near + d * (far - near));
+    } else {
+        z = near * far / (far - d * (far - near));
+    }
+    let t = saturate((z - near) / (far - near));
+    if depth_params.ramp == 1u {
+        return vec4(turbo(t), 1.);
+    }
+    return vec4(vec3(t), 1.);
+}
+
+@fragment
+fn fs_main_uint_copy(vout: VertexOutput) -> @location(0) vec4<u32> {
+    return textureLoad(tex_uint, load_coords(vout.tex_coords, textureDimensions(tex_uint)), 0);
+}
+
+@fragment
+fn fs_main_uint_hash(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let id = textureLoad(tex_uint, load_coords(vout.tex_coords, textureDimensions(tex_uint)), 0);
+    return hash_colour(id);
+}
+
+@fragment
+fn fs_main_sint_copy(vout: VertexOutput) -> @location(0) vec4<i32> {
+    return textureLoad(tex_sint, load_coords(vout.tex_coords, textureDimensions(tex_sint)), 0);
+}
+
+@fragment
+fn fs_main_sint_hash(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let id = textureLoad(tex_sint, load_coords(vout.tex_coords, textureDimensions(tex_sint)), 0);
+    return hash_colour(bitcast<vec4<u32>>(id));
+}
+
+struct YuvParams {
+    kr: f32,
+    kb: f32,
+    y_offset: f32,
+    y_scale: f32,
+    c_scale: f32,
+    space: u32,
+    cosited: vec2<f32>,
+    chroma_filter: u32,
+};
+
+@group(0) @binding(9) var plane_y: texture_2d<f32>;
+@group(0) @binding(10) var plane_uv: texture_2d<f32>;
+@group(0) @binding(11) var plane_v: texture_2d<f32>;
+@group(0) @binding(12) var<uniform> yuv: YuvParams;
+
+// Co-sited chroma samples line up with the left/top luma sample of each pair,
+// half a luma texel away from where a centred sample would be.
+fn chroma_coords(uv: vec2<f32>) -> vec2<f32> {
+    return uv + yuv.cosited * 0.5 / vec2<f32>(textureDimensions(plane_y));
+}
+
+fn yuv_to_rgb(y: f32, cb: f32, cr: f32) -> vec4<f32> {
+    let luma = (y - yuv.y_offset) * yuv.y_scale;
+    let u = (cb - 128. / 255.) * yuv.c_scale;
+    let v = (cr - 128. / 255.) * yuv.c_scale;
+    let kg = 1. - yuv.kr - yuv.kb;
+    let r = luma + 2. * (1. - yuv.kr) * v;
+    let b = luma + 2. * (1. - yuv.kb) * u;
+    let g = (luma - yuv.kr * r - yuv.kb * b) / kg;
+    return decode(yuv.space, vec4(saturate(vec3(r, g, b)), 1.));
+}
+
+@fragment
+fn fs_main_nv12(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let y = textureSample(plane_y, tex_sampler, vout.tex_coords).r;
+    let uv = textureSample(plane_uv, tex_sampler, chroma_coords(vout.tex_coords)).rg;
+    return yuv_to_rgb(y, uv.r, uv.g);
+}
+
+@fragment
+fn fs_main_i420(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let y = textureSample(plane_y, tex_sampler, vout.tex_coords).r;
+    let chroma = chroma_coords(vout.tex_coords);
+    let u = textureSample(plane_uv, tex_sampler, chroma).r;
+    let v = textureSample(plane_v, tex_sampler, chroma).r;
+    return yuv_to_rgb(y, u, v);
+}
+
+fn encode_rgb(coords: vec2<i32>) -> vec3<f32> {
+    let dims = vec2<i32>(textureDimensions(tex));
+    let c = textureLoad(tex, clamp(coords, vec2(0), dims - 1), 0).rgb;
+    return saturate(encode_transfer(yuv.space, to_primaries(yuv.space, c)));
+}
+
+fn rgb_to_yuv(rgb: vec3<f32>) -> vec3<f32> {
+    let kg = 1. - yuv.kr - yuv.kb;
+    let luma = yuv.kr * rgb.r + kg * rgb.g + yuv.kb * rgb.b;
+    let u = (rgb.b - luma) / (2. * (1. - yuv.kb));
+    let v = (rgb.r - luma) / (2. * (1. - yuv.kr));
+    return vec3(
+        luma / yuv.y_scale + yuv.y_offset,
+        vec2(u, v) / yuv.c_scale + 128. / 255.,
+    );
+}
+
+// Weight of the luma texel `offset` away from the even texel of a chroma pair.
+fn chroma_weight(offset: i32, cosited: f32) -> f32 {
+    if yuv.chroma_filter == 0u {
+        return select(0., 1., offset == 0);
+    }
+    if cosited > 0.5 {
+        return select(0.25, 0.5, offset == 0);
+    }
+    return select(0., 0.5, offset >= 0);
+}
+
+// YUV is affine in R'G'B', so filtering before the matrix is equivalent.
+fn encode_chroma(position: vec2<f32>) -> vec2<f32> {
+    let base = vec2<i32>(position) * 2;
+    var rgb = vec3(0.);
+    for (var dy = -1; dy <= 1; dy++) {
+        let wy = chroma_weight(dy, yuv.cosited.y);
+        for (var dx = -1; dx <= 1; dx++) {
+            let weight = wy * chroma_weight(dx, yuv.cosited.x);
+            if weight > 0. {
+                rgb += weight * encode_rgb(base + vec2(dx, dy));
+            }
+        }
+    }
+    return rgb_to_yuv(rgb).yz;
+}
+
+@fragment
+fn fs_main_encode_y(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let luma = rgb_to_yuv(encode_rgb(vec2<i32>(vout.position.xy))).x;
+    return vec4(luma, 0., 0., 1.);
+}
+
+@fragment
+fn fs_main_encode_uv(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(encode_chroma(vout.position.xy), 0., 1.);
+}
+
+@fragment
+fn fs_main_encode_v(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(encode_chroma(vout.position.xy).y, 0., 0., 1.);
+}
+
+@group(0) @binding(13) var<storage, read_write> packed: array<u32>;
+
+// Writes `tex` as RGB9E5 into a buffer laid out for `copy_buffer_to_texture`,
+// since the format can't be rendered to or stored.
+@compute @workgroup_size(8, 8)
+fn cs_main_rgb9e5(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = textureDimensions(tex);
+    if any(id.xy >= dims) {
+        return;
+    }
+    // Rows are padded to COPY_BYTES_PER_ROW_ALIGNMENT, i.e. 64 texels.
+    let stride = (dims.x + 63u) / 64u * 64u;
+    let texel = textureLoad(tex, vec2<i32>(id.xy), 0);
+    packed[id.y * stride + id.x] = linear_to_rgb9e5(texel.rgb);
+}
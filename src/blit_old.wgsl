@@ -61,3 +61,9 @@ fn fs_main_rgbe_to_linear(in: VertexOutput) -> @location(0) vec4<f32> {
     let rgbe = textureSample(r_color, r_sampler, in.tex_coords);
     return vec4(rgbe.rgb * exp2(rgbe.a * 255. - 128.), 1.);
 }
+
+@fragment
+fn fs_main_srgb_to_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+    let rgba = textureSample(r_color, r_sampler, in.tex_coords);
+    return vec4(srgb_to_linear(rgba.rgb), rgba.a);
+}
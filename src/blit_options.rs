@@ -0,0 +1,310 @@
+use crate::{blitter_old::ColourSpace, color_adjust::ColorAdjust, dither, lut::Lut3d};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    A,
+    Zero,
+    One,
+}
+
+/// Source channel written to each of the output's r, g, b and a.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Swizzle(pub [Channel; 4]);
+
+impl Swizzle {
+    pub const IDENTITY: Self = Self([Channel::R, Channel::G, Channel::B, Channel::A]);
+    pub const BGRA: Self = Self([Channel::B, Channel::G, Channel::R, Channel::A]);
+    pub const GRAYSCALE: Self = Self::solo(Channel::R);
+    pub const GRAYSCALE_ALPHA: Self = Self([Channel::R, Channel::R, Channel::R, Channel::G]);
+
+    /// Shows a single channel as opaque grayscale.
+    pub const fn solo(channel: Channel) -> Self {
+        Self([channel, channel, channel, Channel::One])
+    }
+}
+
+impl Default for Swizzle {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Tonemap {
+    #[default]
+    None,
+    Reinhard,
+    /// Reinhard that maps `white` to 1 instead of infinity.
+    ReinhardExtended {
+        white: f32,
+    },
+    AcesFitted,
+    AgX,
+    /// Uncharted 2 filmic curve.
+    Hable,
+}
+
+impl Tonemap {
+    fn index(&self) -> u32 {
+        match self {
+            Tonemap::None => 0,
+            Tonemap::Reinhard => 1,
+            Tonemap::ReinhardExtended { .. } => 2,
+            Tonemap::AcesFitted => 3,
+            Tonemap::AgX => 4,
+            Tonemap::Hable => 5,
+        }
+    }
+}
+
+/// How colours outside the target gamut are brought back in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GamutMapping {
+    #[default]
+    Clip,
+    /// Blends towards grey of the same luminance.
+    Desaturate,
+    /// Reduces OkLCh chroma, keeping lightness and hue.
+    Perceptual,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LutInterpolation {
+    #[default]
+    Trilinear,
+    Tetrahedral,
+}
+
+/// Colour grading through a 3D LUT, applied after tone mapping.
+#[derive(Copy, Clone, Debug)]
+pub struct LutStage<'a> {
+    pub lut: &'a Lut3d,
+    pub interpolation: LutInterpolation,
+    /// Encoding the LUT expects its input in and produces its output in.
+    pub space: ColourSpace,
+}
+
+impl PartialEq for LutStage<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.lut, other.lut)
+            && self.interpolation == other.interpolation
+            && self.space == other.space
+    }
+}
+
+/// Noise added right before the target quantises, tiled in screen space.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Dither {
+    #[default]
+    None,
+    Bayer4x4,
+    Bayer8x8,
+    BlueNoise,
+    /// Triangular-PDF white noise spanning two quantisation steps.
+    Triangular,
+}
+
+/// How stored pixels are turned for display, as in the EXIF tag.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    #[default]
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    /// Mirrored across the top-left to bottom-right diagonal.
+    Transpose,
+    /// A quarter turn clockwise.
+    Rotate90,
+    /// Mirrored across the top-right to bottom-left diagonal.
+    Transverse,
+    Rotate270,
+}
+
+impl Orientation {
+    /// From the EXIF `Orientation` value, 1 to 8.
+    pub fn from_exif(value: u32) -> Option<Self> {
+        const ALL: [Orientation; 8] = [
+            Orientation::Normal,
+            Orientation::FlipHorizontal,
+            Orientation::Rotate180,
+            Orientation::FlipVertical,
+            Orientation::Transpose,
+            Orientation::Rotate90,
+            Orientation::Transverse,
+            Orientation::Rotate270,
+        ];
+        ALL.get(value.checked_sub(1)? as usize).copied()
+    }
+
+    /// Whether the displayed width is the stored height.
+    pub fn swaps_dimensions(self) -> bool {
+        self as u32 >= Orientation::Transpose as u32
+    }
+
+    /// Where a point of the stored image, in [0, 1] coordinates, is shown.
+    pub fn stored_to_display(self, [u, v]: [f32; 2]) -> [f32; 2] {
+        match self {
+            Orientation::Normal => [u, v],
+            Orientation::FlipHorizontal => [1. - u, v],
+            Orientation::Rotate180 => [1. - u, 1. - v],
+            Orientation::FlipVertical => [u, 1. - v],
+            Orientation::Transpose => [v, u],
+            Orientation::Rotate90 => [1. - v, u],
+            Orientation::Transverse => [1. - v, 1. - u],
+            Orientation::Rotate270 => [v, 1. - u],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BlitOptions<'a> {
+    pub swizzle: Swizzle,
+    /// Applied to the source coordinates; a quarter turn stretches the image
+    /// unless the viewport's aspect ratio is swapped too.
+    pub orientation: Orientation,
+    pub source_space: ColourSpace,
+    /// Encoding written to the target. Leave at `Linear` for `*Srgb` formats,
+    /// which encode in hardware. `Linear` is clamped to [0, 1] only for
+    /// fixed-point targets; float targets keep values above 1, up to what the
+    /// format holds. `Rgbe` packs shared-exponent texels into an `Rgba8Unorm`
+    /// target.
+    pub target_space: ColourSpace,
+    pub gamut_mapping: GamutMapping,
+    /// Stripes pixels that were out of the target gamut in magenta.
+    pub highlight_out_of_gamut: bool,
+    /// In stops, applied to linear values before tone mapping.
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    /// Applied to display-linear values after tone mapping.
+    pub color_adjust: ColorAdjust,
+    pub lut: Option<LutStage<'a>>,
+    pub dither: Dither,
+    /// Changing this every frame animates the dither pattern.
+    pub dither_frame: u32,
+}
+
+/// Largest value a `Linear` target holds.
+fn linear_peak(format: wgpu::TextureFormat) -> f32 {
+    use wgpu::TextureFormat as F;
+    match format {
+        F::R16Float | F::Rg16Float | F::Rgba16Float => half::f16::MAX.to_f32(),
+        F::Rg11b10Float => 65024.,
+        F::R32Float | F::Rg32Float | F::Rgba32Float => f32::MAX,
+        _ => 1.,
+    }
+}
+
+impl BlitOptions<'_> {
+    pub fn is_passthrough(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn params(&self, target_format: wgpu::TextureFormat) -> BlitParams {
+        BlitParams {
+            swizzle: self.swizzle.0.map(|c| c as u32),
+            exposure: self.exposure.exp2(),
+            tonemap: self.tonemap.index(),
+            white: match self.tonemap {
+                Tonemap::ReinhardExtended { white } => white,
+                _ => 1.,
+            },
+            source_space: self.source_space as u32,
+            target_space: self.target_space as u32,
+            gamut_mapping: self.gamut_mapping as u32,
+            highlight_gamut: self.highlight_out_of_gamut as u32,
+            lut_space: self.lut.map_or(0, |stage| stage.space as u32),
+            lut_interpolation: self.lut.map_or(0, |stage| stage.interpolation as u32),
+            dither: self.dither as u32,
+            dither_frame: self.dither_frame,
+            // Noise in an RGBE exponent would scale whole pixels.
+            dither_step: match self.target_space {
+                ColourSpace::Rgbe => 0.,
+                _ => dither::quantisation_step(target_format),
+            },
+            dither_srgb: target_format.is_srgb() as u32,
+            orientation: self.orientation as u32,
+            linear_peak: linear_peak(target_format),
+            _padding: 0,
+            color_matrix: self.color_adjust.matrix,
+            color_offset: self.color_adjust.offset,
+        }
+    }
+}
+
+/// Mirrors `Options` in `blit_new.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct BlitParams {
+    swizzle: [u32; 4],
+    exposure: f32,
+    tonemap: u32,
+    white: f32,
+    source_space: u32,
+    target_space: u32,
+    gamut_mapping: u32,
+    highlight_gamut: u32,
+    lut_space: u32,
+    lut_interpolation: u32,
+    dither: u32,
+    dither_frame: u32,
+    dither_step: f32,
+    dither_srgb: u32,
+    orientation: u32,
+    linear_peak: f32,
+    _padding: u32,
+    color_matrix: [[f32; 4]; 4],
+    color_offset: [f32; 4],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orientation_from_exif() {
+        assert_eq!(Orientation::from_exif(0), None);
+        assert_eq!(Orientation::from_exif(9), None);
+        assert_eq!(Orientation::from_exif(1), Some(Orientation::Normal));
+        assert_eq!(Orientation::from_exif(3), Some(Orientation::Rotate180));
+        assert_eq!(Orientation::from_exif(6), Some(Orientation::Rotate90));
+        assert_eq!(Orientation::from_exif(8), Some(Orientation::Rotate270));
+        let swapped: Vec<_> = (1..=8)
+            .filter(|&v| Orientation::from_exif(v).unwrap().swaps_dimensions())
+            .collect();
+        assert_eq!(swapped, [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn quarter_turns_move_corners() {
+        // The stored top-left corner ends up top-right after a clockwise turn.
+        assert_eq!(Orientation::Rotate90.stored_to_display([0., 0.]), [1., 0.]);
+        assert_eq!(Orientation::Rotate270.stored_to_display([0., 0.]), [0., 1.]);
+        assert_eq!(Orientation::Transpose.stored_to_display([1., 0.]), [0., 1.]);
+    }
+
+    #[test]
+    fn params_match_shader_layout() {
+        let module = naga::front::wgsl::parse_str(include_str!("blit_new.wgsl")).unwrap();
+        let (handle, _) = module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some("Options"))
+            .unwrap();
+        let naga::TypeInner::Struct { ref members, span } = module.types[handle].inner else {
+            unreachable!();
+        };
+        assert_eq!(span as usize, std::mem::size_of::<BlitParams>());
+        let matrix = members
+            .iter()
+            .find(|m| m.name.as_deref() == Some("color_matrix"));
+        // The matrix and offset close the struct.
+        assert_eq!(
+            matrix.unwrap().offset as usize,
+            std::mem::size_of::<BlitParams>() - 80
+        );
+    }
+}
@@ -1,10 +1,214 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+};
+
+use anyhow::{Context, Result};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    blit_options::{BlitOptions, Dither, Orientation},
+    dither, hot_reload,
+    subresource::Subresource,
+    tiled::TiledTexture,
+    yuv::{ChromaFilter, YuvFormat, YuvFrame, YuvPlanes},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResolveFilter {
+    /// Hardware resolve through a `resolve_target`. The source needs
+    /// `RENDER_ATTACHMENT` usage.
+    Builtin,
+    Box,
+    /// Averages samples after a `c / (1 + max(c))` tonemap and inverts it
+    /// afterwards, so HDR highlights don't bleed over edges.
+    TonemappedAverage,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColourRamp {
+    #[default]
+    Grayscale,
+    Turbo,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DepthVisualisation {
+    pub near: f32,
+    pub far: f32,
+    pub reverse_z: bool,
+    pub ramp: ColourRamp,
+}
+
+impl Default for DepthVisualisation {
+    fn default() -> Self {
+        Self {
+            near: 0.1,
+            far: 100.,
+            reverse_z: false,
+            ramp: ColourRamp::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IntegerBlit {
+    /// Writes raw values into a target of the same signedness.
+    Copy,
+    /// Maps every distinct value to a stable colour, zero stays black.
+    HashColour,
+}
+
+/// Channels an integer format stores; loads fill in the rest.
+fn channel_count(format: wgpu::TextureFormat) -> u32 {
+    use wgpu::TextureFormat as F;
+    match format {
+        F::R8Uint | F::R8Sint | F::R16Uint | F::R16Sint | F::R32Uint | F::R32Sint => 1,
+        F::Rg8Uint | F::Rg8Sint | F::Rg16Uint | F::Rg16Sint | F::Rg32Uint | F::Rg32Sint => 2,
+        _ => 4,
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthParams {
+    near: f32,
+    far: f32,
+    reverse_z: u32,
+    ramp: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum SourceKind {
+    D2,
+    D3,
+    ResolveBox,
+    ResolveTonemapped,
+    Depth,
+    Integer {
+        signed: bool,
+        mode: IntegerBlit,
+    },
+    Nv12,
+    I420,
+    YuvEncode(EncodePlane),
+    /// A [`TiledTexture`] tile; sampled like `D2` after `vs_main_tile`.
+    Tile,
+}
+
+/// Plane of a [`YuvFrame`] rendered from RGB. I420's U plane uses `Uv`, as
+/// `R8Unorm` keeps only the first channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum EncodePlane {
+    Y,
+    Uv,
+    V,
+}
+
+impl SourceKind {
+    fn of(texture: &wgpu::Texture) -> Self {
+        match texture.dimension() {
+            wgpu::TextureDimension::D3 => SourceKind::D3,
+            _ => SourceKind::D2,
+        }
+    }
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            SourceKind::D2 | SourceKind::Tile => "fs_main",
+            SourceKind::D3 => "fs_main_3d",
+            SourceKind::ResolveBox => "fs_main_resolve_box",
+            SourceKind::ResolveTonemapped => "fs_main_resolve_tonemapped",
+            SourceKind::Depth => "fs_main_depth",
+            SourceKind::Integer { signed, mode } => match (signed, mode) {
+                (false, IntegerBlit::Copy) => "fs_main_uint_copy",
+                (false, IntegerBlit::HashColour) => "fs_main_uint_hash",
+                (true, IntegerBlit::Copy) => "fs_main_sint_copy",
+                (true, IntegerBlit::HashColour) => "fs_main_sint_hash",
+            },
+            SourceKind::Nv12 => "fs_main_nv12",
+            SourceKind::I420 => "fs_main_i420",
+            SourceKind::YuvEncode(EncodePlane::Y) => "fs_main_encode_y",
+            SourceKind::YuvEncode(EncodePlane::Uv) => "fs_main_encode_uv",
+            SourceKind::YuvEncode(EncodePlane::V) => "fs_main_encode_v",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    format: wgpu::TextureFormat,
+    source: SourceKind,
+    sample_count: u32,
+    /// `fs_main_options`. The swizzle, solo channel and other `BlitOptions`
+    /// are uniforms rather than part of the key: without overridable
+    /// constants each swizzle would need its own shader module.
+    options: bool,
+    lut: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BlitTarget<'a> {
+    pub view: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub resolve_target: Option<&'a wgpu::TextureView>,
+}
+
+impl<'a> BlitTarget<'a> {
+    pub fn new(view: &'a wgpu::TextureView, format: wgpu::TextureFormat) -> Self {
+        Self {
+            view,
+            format,
+            sample_count: 1,
+            resolve_target: None,
+        }
+    }
+
+    pub fn multisampled(
+        mut self,
+        sample_count: u32,
+        resolve_target: Option<&'a wgpu::TextureView>,
+    ) -> Self {
+        self.sample_count = sample_count;
+        self.resolve_target = resolve_target;
+        self
+    }
+
+    fn key(&self, source: SourceKind) -> PipelineKey {
+        PipelineKey {
+            format: self.format,
+            source,
+            sample_count: self.sample_count,
+            options: false,
+            lut: false,
+        }
+    }
+}
 
 pub struct Blitter {
-    pipelines: RefCell<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+    pipelines: RefCell<HashMap<PipelineKey, wgpu::RenderPipeline>>,
     shader: wgpu::ShaderModule,
     bind_group_layout: wgpu::BindGroupLayout,
+    bind_group_layout_3d: wgpu::BindGroupLayout,
+    bind_group_layout_ms: wgpu::BindGroupLayout,
+    bind_group_layout_depth: wgpu::BindGroupLayout,
+    bind_group_layout_uint: wgpu::BindGroupLayout,
+    bind_group_layout_sint: wgpu::BindGroupLayout,
+    bind_group_layout_nv12: wgpu::BindGroupLayout,
+    bind_group_layout_i420: wgpu::BindGroupLayout,
+    bind_group_layout_yuv_encode: wgpu::BindGroupLayout,
+    bind_group_layout_tile: wgpu::BindGroupLayout,
+    options_layout: wgpu::BindGroupLayout,
+    lut_layout: wgpu::BindGroupLayout,
     sampler: wgpu::Sampler,
+    clamp_sampler: wgpu::Sampler,
+    /// Built on first use by `Dither::BlueNoise`; `no_noise` is bound
+    /// otherwise.
+    blue_noise: OnceCell<wgpu::Buffer>,
+    no_noise: wgpu::Buffer,
+    rgb9e5_layout: wgpu::BindGroupLayout,
+    rgb9e5_pipeline: OnceCell<wgpu::ComputePipeline>,
 }
 
 impl Blitter {
@@ -24,6 +228,12 @@ impl Blitter {
             mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
+        let sampler_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Blit Bind Group Layout"),
             entries: &[
@@ -37,25 +247,305 @@ impl Blitter {
                     },
                     count: None,
                 },
+                sampler_entry,
+            ],
+        });
+        let bind_group_layout_3d =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit 3D Bind Group Layout"),
+                entries: &[
+                    sampler_entry,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let bind_group_layout_ms =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit Multisampled Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: true,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 15,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let bind_group_layout_depth =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit Depth Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let integer_layout = |binding, sample_type, label| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 16,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        };
+        let bind_group_layout_uint = integer_layout(
+            7,
+            wgpu::TextureSampleType::Uint,
+            "Blit Uint Bind Group Layout",
+        );
+        let bind_group_layout_sint = integer_layout(
+            8,
+            wgpu::TextureSampleType::Sint,
+            "Blit Sint Bind Group Layout",
+        );
+        let plane_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let yuv_params_entry = wgpu::BindGroupLayoutEntry {
+            binding: 12,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_nv12 =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit NV12 Bind Group Layout"),
+                entries: &[
+                    sampler_entry,
+                    plane_entry(9),
+                    plane_entry(10),
+                    yuv_params_entry,
+                ],
+            });
+        let bind_group_layout_i420 =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit I420 Bind Group Layout"),
+                entries: &[
+                    sampler_entry,
+                    plane_entry(9),
+                    plane_entry(10),
+                    plane_entry(11),
+                    yuv_params_entry,
+                ],
+            });
+        let bind_group_layout_yuv_encode =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit YUV Encode Bind Group Layout"),
+                entries: &[plane_entry(0), yuv_params_entry],
+            });
+        let bind_group_layout_tile =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit Tile Bind Group Layout"),
+                entries: &[
+                    plane_entry(0),
+                    sampler_entry,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 14,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ..yuv_params_entry
+                    },
+                ],
+            });
+        let rgb9e5_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("RGB9E5 Pack Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ..plane_entry(0)
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let options_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Blit Options Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(dither::BLUE_NOISE_BYTES),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Blit LUT Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
-        let pipelines = RefCell::new(HashMap::from([(
-            wgpu::TextureFormat::Bgra8UnormSrgb,
-            Self::create_pipeline(device, &shader, wgpu::TextureFormat::Bgra8UnormSrgb),
-        )]));
+        let clamp_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Blit Clamp Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
 
-        Self {
-            pipelines,
+        let blitter = Self {
+            pipelines: RefCell::default(),
             shader,
             bind_group_layout,
+            bind_group_layout_3d,
+            bind_group_layout_ms,
+            bind_group_layout_depth,
+            bind_group_layout_uint,
+            bind_group_layout_sint,
+            bind_group_layout_nv12,
+            bind_group_layout_i420,
+            bind_group_layout_yuv_encode,
+            bind_group_layout_tile,
+            options_layout,
+            lut_layout,
             sampler,
-        }
+            clamp_sampler,
+            blue_noise: OnceCell::new(),
+            no_noise: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("No Noise"),
+                size: dither::BLUE_NOISE_BYTES,
+                usage: wgpu::BufferUsages::UNIFORM,
+                mapped_at_creation: false,
+            }),
+            rgb9e5_layout,
+            rgb9e5_pipeline: OnceCell::new(),
+        };
+        let key = PipelineKey {
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            source: SourceKind::D2,
+            sample_count: 1,
+            options: false,
+            lut: false,
+        };
+        blitter
+            .pipelines
+            .borrow_mut()
+            .insert(key, blitter.create_pipeline(device, &blitter.shader, key));
+        blitter
     }
 
     pub fn blit_to_texture(
@@ -65,14 +555,657 @@ impl Blitter {
         src_texture: &wgpu::TextureView,
         dst_texture: &wgpu::TextureView,
         dst_format: wgpu::TextureFormat,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        self.blit_to_target(
+            encoder,
+            device,
+            src_texture,
+            BlitTarget::new(dst_texture, dst_format),
+            viewport,
+        );
+    }
+
+    pub fn blit_to_target(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src_texture: &wgpu::TextureView,
+        target: BlitTarget,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        self.blit_with_options(
+            encoder,
+            device,
+            src_texture,
+            target,
+            &BlitOptions::default(),
+            viewport,
+        );
+    }
+
+    pub fn blit_with_options(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src_texture: &wgpu::TextureView,
+        target: BlitTarget,
+        options: &BlitOptions,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        let texture_bind_group = self.create_bind_group(device, src_texture);
+        let option_bind_groups = self.option_bind_groups(device, options, target.format);
+        let bind_groups: Vec<_> = std::iter::once(&texture_bind_group)
+            .chain(&option_bind_groups)
+            .collect();
+        self.draw(
+            encoder,
+            device,
+            PipelineKey {
+                options: !option_bind_groups.is_empty(),
+                lut: option_bind_groups.len() == 2,
+                ..target.key(SourceKind::D2)
+            },
+            &bind_groups,
+            target,
+            viewport,
+        );
+    }
+
+    /// Draws every tile of `src` into its part of `viewport`, so the tiles
+    /// read as one image. The orientation in `options` moves whole tiles as
+    /// well as turning each one.
+    pub fn blit_tiled(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src: &TiledTexture,
+        target: BlitTarget,
+        options: &BlitOptions,
         (x, y, w, h): (f32, f32, f32, f32),
     ) {
-        let mut pipelines = self.pipelines.borrow_mut();
-        let pipeline = pipelines
-            .entry(dst_format)
-            .or_insert_with_key(|&format| Self::create_pipeline(device, &self.shader, format));
+        let orientation = options.orientation;
+        let options = BlitOptions {
+            orientation: Orientation::Normal,
+            ..*options
+        };
+        let option_bind_groups = self.option_bind_groups(device, &options, target.format);
+        let key = PipelineKey {
+            options: !option_bind_groups.is_empty(),
+            lut: option_bind_groups.len() == 2,
+            ..target.key(SourceKind::Tile)
+        };
+        let (width, height) = (src.width as f32, src.height as f32);
+        for tile in &src.tiles {
+            let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Blit Tile Params"),
+                contents: bytemuck::bytes_of(&tile.params(orientation)),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Blit Tile Bind Group"),
+                layout: &self.bind_group_layout_tile,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&tile.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.clamp_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 14,
+                        resource: params.as_entire_binding(),
+                    },
+                ],
+            });
+            let bind_groups: Vec<_> = std::iter::once(&texture_bind_group)
+                .chain(&option_bind_groups)
+                .collect();
+
+            let core = tile.core;
+            let [a, b] = [
+                [core.x as f32 / width, core.y as f32 / height],
+                [
+                    (core.x + core.width) as f32 / width,
+                    (core.y + core.height) as f32 / height,
+                ],
+            ]
+            .map(|corner| orientation.stored_to_display(corner));
+            let (x0, x1) = (x + a[0].min(b[0]) * w, x + a[0].max(b[0]) * w);
+            let (y0, y1) = (y + a[1].min(b[1]) * h, y + a[1].max(b[1]) * h);
+            self.draw(
+                encoder,
+                device,
+                key,
+                &bind_groups,
+                target,
+                (x0, y0, x1 - x0, y1 - y0),
+            );
+        }
+    }
+
+    /// The options and LUT bind groups, or none for a passthrough blit.
+    fn option_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        options: &BlitOptions,
+        target_format: wgpu::TextureFormat,
+    ) -> Vec<wgpu::BindGroup> {
+        if options.is_passthrough() {
+            return Vec::new();
+        }
+
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit Options"),
+            contents: bytemuck::bytes_of(&options.params(target_format)),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let options_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit Options Bind Group"),
+            layout: &self.options_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.noise(device, options.dither).as_entire_binding(),
+                },
+            ],
+        });
+        let Some(stage) = options.lut else {
+            return vec![options_bind_group];
+        };
+        let lut_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit LUT Bind Group"),
+            layout: &self.lut_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&stage.lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.clamp_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: stage.lut.domain.as_entire_binding(),
+                },
+            ],
+        });
+        vec![options_bind_group, lut_bind_group]
+    }
+
+    /// Resolves a multisampled `src` while blitting it into `target`.
+    pub fn resolve_to_target(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src: &wgpu::Texture,
+        filter: ResolveFilter,
+        target: BlitTarget,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        assert!(
+            src.sample_count() > 1,
+            "Blitter: resolve source is not multisampled"
+        );
+        let source = match filter {
+            ResolveFilter::Builtin => {
+                let resolved = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Blit Resolve Scratch"),
+                    size: src.size(),
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: src.format(),
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                });
+                let resolved_view = resolved.create_view(&Default::default());
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Blit Resolve Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &src.create_view(&Default::default()),
+                        resolve_target: Some(&resolved_view),
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+                self.blit_to_target(encoder, device, &resolved_view, target, viewport);
+                return;
+            }
+            ResolveFilter::Box => SourceKind::ResolveBox,
+            ResolveFilter::TonemappedAverage => SourceKind::ResolveTonemapped,
+        };
+
+        let samples = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit Resolve Samples"),
+            contents: bytemuck::bytes_of(&[src.sample_count(), 0, 0, 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit Resolve Bind Group"),
+            layout: &self.bind_group_layout_ms,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(
+                        &src.create_view(&Default::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 15,
+                    resource: samples.as_entire_binding(),
+                },
+            ],
+        });
+        self.draw(
+            encoder,
+            device,
+            target.key(source),
+            &[&texture_bind_group],
+            target,
+            viewport,
+        );
+    }
+
+    /// Linearises the depth aspect of `src` and maps it onto a colour ramp.
+    pub fn visualise_depth(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src: &wgpu::Texture,
+        vis: DepthVisualisation,
+        target: BlitTarget,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        assert!(
+            src.format().has_depth_aspect(),
+            "Blitter: {:?} has no depth aspect",
+            src.format()
+        );
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit Depth Params"),
+            contents: bytemuck::bytes_of(&DepthParams {
+                near: vis.near,
+                far: vis.far,
+                reverse_z: vis.reverse_z as u32,
+                ramp: vis.ramp as u32,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let view = src.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Blit Depth View"),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            mip_level_count: Some(1),
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit Depth Bind Group"),
+            layout: &self.bind_group_layout_depth,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+        self.draw(
+            encoder,
+            device,
+            target.key(SourceKind::Depth),
+            &[&texture_bind_group],
+            target,
+            viewport,
+        );
+    }
 
+    /// Blits a `Uint`/`Sint` texture, which can't go through a sampler.
+    pub fn blit_integer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src: &wgpu::Texture,
+        mode: IntegerBlit,
+        target: BlitTarget,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        let (signed, binding, layout) = match src.format().sample_type(None) {
+            Some(wgpu::TextureSampleType::Uint) => (false, 7, &self.bind_group_layout_uint),
+            Some(wgpu::TextureSampleType::Sint) => (true, 8, &self.bind_group_layout_sint),
+            _ => panic!("Blitter: {:?} is not an integer format", src.format()),
+        };
+        if mode == IntegerBlit::Copy {
+            assert_eq!(
+                src.format().sample_type(None),
+                target.format.sample_type(None),
+                "Blitter: integer copy from {:?} into {:?}",
+                src.format(),
+                target.format
+            );
+        }
+        let channels = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit Integer Channels"),
+            contents: bytemuck::bytes_of(&[channel_count(src.format()), 0, 0, 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
         let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit Integer Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(&src.create_view(
+                        &wgpu::TextureViewDescriptor {
+                            mip_level_count: Some(1),
+                            array_layer_count: Some(1),
+                            ..Default::default()
+                        },
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 16,
+                    resource: channels.as_entire_binding(),
+                },
+            ],
+        });
+        self.draw(
+            encoder,
+            device,
+            target.key(SourceKind::Integer { signed, mode }),
+            &[&texture_bind_group],
+            target,
+            viewport,
+        );
+    }
+
+    /// Converts a planar 4:2:0 YUV frame to RGB while blitting it.
+    pub fn blit_yuv(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        planes: YuvPlanes,
+        format: &YuvFormat,
+        target: BlitTarget,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit YUV Params"),
+            contents: bytemuck::bytes_of(&format.params()),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let sampler_entry = wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&self.clamp_sampler),
+        };
+        let plane_entry = |binding, view| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        };
+        let params_entry = wgpu::BindGroupEntry {
+            binding: 12,
+            resource: params.as_entire_binding(),
+        };
+        let (source, texture_bind_group) = match planes {
+            YuvPlanes::Nv12 { y, uv } => (
+                SourceKind::Nv12,
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Blit NV12 Bind Group"),
+                    layout: &self.bind_group_layout_nv12,
+                    entries: &[
+                        sampler_entry,
+                        plane_entry(9, y),
+                        plane_entry(10, uv),
+                        params_entry,
+                    ],
+                }),
+            ),
+            YuvPlanes::I420 { y, u, v } => (
+                SourceKind::I420,
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Blit I420 Bind Group"),
+                    layout: &self.bind_group_layout_i420,
+                    entries: &[
+                        sampler_entry,
+                        plane_entry(9, y),
+                        plane_entry(10, u),
+                        plane_entry(11, v),
+                        params_entry,
+                    ],
+                }),
+            ),
+        };
+        self.draw(
+            encoder,
+            device,
+            target.key(source),
+            &[&texture_bind_group],
+            target,
+            viewport,
+        );
+    }
+
+    /// Converts `src`, which must match the frame size, to the frame's YUV
+    /// planes. Call [`YuvFrame::copy_to_staging`] afterwards to read them back.
+    pub fn encode_yuv(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src: &wgpu::TextureView,
+        format: &YuvFormat,
+        filter: ChromaFilter,
+        frame: &YuvFrame,
+    ) {
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit YUV Encode Params"),
+            contents: bytemuck::bytes_of(&format.encode_params(filter)),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit YUV Encode Bind Group"),
+            layout: &self.bind_group_layout_yuv_encode,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(src),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+        let plane_kinds = [EncodePlane::Y, EncodePlane::Uv, EncodePlane::V];
+        for (plane, kind) in frame.planes.iter().zip(plane_kinds) {
+            let target = BlitTarget::new(&plane.view, plane.format);
+            self.draw(
+                encoder,
+                device,
+                target.key(SourceKind::YuvEncode(kind)),
+                &[&bind_group],
+                target,
+                (0., 0., plane.width as f32, plane.height as f32),
+            );
+        }
+    }
+
+    /// Packs `src` into `dst`, an `Rgb9e5Ufloat` texture of the same size with
+    /// `COPY_DST`. The format isn't renderable, so this runs a compute pass
+    /// into a buffer and copies that. Needs compute shader support.
+    pub fn encode_rgb9e5(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src: &wgpu::TextureView,
+        dst: &wgpu::Texture,
+    ) {
+        assert_eq!(dst.format(), wgpu::TextureFormat::Rgb9e5Ufloat);
+        let size = wgpu::Extent3d {
+            depth_or_array_layers: 1,
+            ..dst.size()
+        };
+        let padded_row = wgpu::util::align_to(size.width * 4, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let packed = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("RGB9E5 Packed"),
+            size: padded_row as u64 * size.height as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("RGB9E5 Pack Bind Group"),
+            layout: &self.rgb9e5_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(src),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: packed.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline = self
+            .rgb9e5_pipeline
+            .get_or_init(|| self.create_rgb9e5_pipeline(device, &self.shader));
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("RGB9E5 Pack Pass"),
+            });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(size.width.div_ceil(8), size.height.div_ceil(8), 1);
+        }
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &packed,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            dst.as_image_copy(),
+            size,
+        );
+    }
+
+    /// Blits one mip level / layer / cube face / 3D slice of `src` over the
+    /// whole of the chosen subresource of `dst`, reinterpreting both through
+    /// their textures' own formats.
+    pub fn blit_subresource(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        src: &wgpu::Texture,
+        src_sub: Subresource,
+        dst: &wgpu::Texture,
+        dst_sub: Subresource,
+    ) {
+        let source = SourceKind::of(src);
+        let src_view = src_sub.source_view(src, src.format());
+        let texture_bind_group = match source {
+            SourceKind::D3 => {
+                let slice = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Blit Slice Uniform"),
+                    contents: bytemuck::bytes_of(&[src_sub.slice_coord(src), 0., 0., 0.]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Blit Subresource 3D Bind Group"),
+                    layout: &self.bind_group_layout_3d,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&src_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: slice.as_entire_binding(),
+                        },
+                    ],
+                })
+            }
+            _ => self.create_bind_group(device, &src_view),
+        };
+
+        let extent = dst_sub.extent(dst);
+        let viewport = (0., 0., extent.width as f32, extent.height as f32);
+        dst_sub.render_into(device, encoder, dst, dst.format(), |encoder, dst_view| {
+            let target = BlitTarget::new(dst_view, dst.format());
+            self.draw(
+                encoder,
+                device,
+                target.key(source),
+                &[&texture_bind_group],
+                target,
+                viewport,
+            )
+        });
+    }
+
+    /// Swaps in new `blit_new.wgsl` source and rebuilds every pipeline built so
+    /// far. On any error the previous shader and pipelines are kept.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<()> {
+        hot_reload::validate_wgsl("blit_new.wgsl", source)?;
+        let (shader, pipelines, rgb9e5_pipeline) = hot_reload::try_build(device, || {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Blit Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            let pipelines: HashMap<_, _> = self
+                .pipelines
+                .borrow()
+                .keys()
+                .map(|&key| (key, self.create_pipeline(device, &shader, key)))
+                .collect();
+            let rgb9e5_pipeline: OnceCell<_> = match self.rgb9e5_pipeline.get() {
+                Some(_) => self.create_rgb9e5_pipeline(device, &shader).into(),
+                None => OnceCell::new(),
+            };
+            (shader, pipelines, rgb9e5_pipeline)
+        })
+        .context("New blit shader doesn't fit its pipelines")?;
+        self.shader = shader;
+        self.pipelines = RefCell::new(pipelines);
+        self.rgb9e5_pipeline = rgb9e5_pipeline;
+        Ok(())
+    }
+
+    fn noise(&self, device: &wgpu::Device, dither: Dither) -> &wgpu::Buffer {
+        match dither {
+            Dither::BlueNoise => self
+                .blue_noise
+                .get_or_init(|| dither::create_blue_noise_buffer(device)),
+            _ => &self.no_noise,
+        }
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        src_texture: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &self.bind_group_layout,
             entries: &[
@@ -85,13 +1218,28 @@ impl Blitter {
                     resource: wgpu::BindingResource::Sampler(&self.sampler),
                 },
             ],
-        });
+        })
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        key: PipelineKey,
+        bind_groups: &[&wgpu::BindGroup],
+        target: BlitTarget,
+        (x, y, w, h): (f32, f32, f32, f32),
+    ) {
+        let mut pipelines = self.pipelines.borrow_mut();
+        let pipeline = pipelines
+            .entry(key)
+            .or_insert_with_key(|&key| self.create_pipeline(device, &self.shader, key));
 
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Blit Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: dst_texture,
-                resolve_target: None,
+                view: target.view,
+                resolve_target: target.resolve_target,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Load,
                     store: true,
@@ -102,34 +1250,83 @@ impl Blitter {
 
         render_pass.set_pipeline(pipeline);
         render_pass.set_viewport(x, y, w, h, 0., 1.);
-        render_pass.set_bind_group(0, &texture_bind_group, &[]);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
         render_pass.draw(0..3, 0..1);
     }
 
+    fn create_rgb9e5_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::ComputePipeline {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("RGB9E5 Pack Pipeline Layout"),
+            bind_group_layouts: &[&self.rgb9e5_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("RGB9E5 Pack Pipeline"),
+            layout: Some(&layout),
+            module: shader,
+            entry_point: "cs_main_rgb9e5",
+        })
+    }
+
     fn create_pipeline(
+        &self,
         device: &wgpu::Device,
         shader: &wgpu::ShaderModule,
-        format: wgpu::TextureFormat,
+        key: PipelineKey,
     ) -> wgpu::RenderPipeline {
+        let bind_group_layout = match key.source {
+            SourceKind::D2 => &self.bind_group_layout,
+            SourceKind::D3 => &self.bind_group_layout_3d,
+            SourceKind::ResolveBox | SourceKind::ResolveTonemapped => &self.bind_group_layout_ms,
+            SourceKind::Depth => &self.bind_group_layout_depth,
+            SourceKind::Integer { signed: false, .. } => &self.bind_group_layout_uint,
+            SourceKind::Integer { signed: true, .. } => &self.bind_group_layout_sint,
+            SourceKind::Nv12 => &self.bind_group_layout_nv12,
+            SourceKind::I420 => &self.bind_group_layout_i420,
+            SourceKind::YuvEncode(_) => &self.bind_group_layout_yuv_encode,
+            SourceKind::Tile => &self.bind_group_layout_tile,
+        };
+        let bind_group_layouts = [bind_group_layout, &self.options_layout, &self.lut_layout];
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &bind_group_layouts[..1 + key.options as usize + key.lut as usize],
+            push_constant_ranges: &[],
+        });
         device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Blit Pipeline"),
-            layout: None,
+            layout: Some(&layout),
             vertex: wgpu::VertexState {
                 module: shader,
-                entry_point: "vs_main",
+                entry_point: match key.source {
+                    SourceKind::Tile => "vs_main_tile",
+                    _ => "vs_main",
+                },
                 buffers: &[],
             },
             fragment: Some(wgpu::FragmentState {
                 module: shader,
-                entry_point: "fs_main",
-                targets: &[Some(format.into())],
+                entry_point: match (key.options, key.lut) {
+                    (true, true) => "fs_main_options_lut",
+                    (true, false) => "fs_main_options",
+                    _ => key.source.entry_point(),
+                },
+                targets: &[Some(key.format.into())],
             }),
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 ..Default::default()
             },
             depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
+            multisample: wgpu::MultisampleState {
+                count: key.sample_count,
+                ..Default::default()
+            },
             multiview: None,
         })
     }
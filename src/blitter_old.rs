@@ -1,7 +1,52 @@
-#[derive(Copy, Clone, Debug)]
+use anyhow::{bail, Result};
+
+use crate::subresource::Subresource;
+
+/// Pixel encoding of a texture. Everything is decoded into linear Rec.709
+/// with 1.0 at SDR reference white. [`Blitter`] only handles `Linear`,
+/// `Rgbe`, `Srgb` and `ScRgb` sources; `blitter_new` decodes them all.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub enum ColourSpace {
+    #[default]
     Linear,
     Rgbe,
+    /// sRGB-encoded data in a non-`*Srgb` format.
+    Srgb,
+    /// P3-D65 primaries with the sRGB transfer curve.
+    DisplayP3,
+    /// Rec.2020 primaries with a BT.1886 2.4 gamma.
+    Rec2020,
+    /// BT.2100 PQ, 203 nits reference white.
+    Pq,
+    /// BT.2100 HLG, ignoring the OOTF.
+    Hlg,
+    /// Linear Rec.709 that may exceed [0, 1], for float targets.
+    ScRgb,
+    /// Adobe RGB (1998): wider greens and a 2.2 gamma.
+    AdobeRgb,
+}
+
+/// Fragment shader converting `src_space` for a `dest_format` target. Only
+/// linear, sRGB, RGBE and scRGB sources are handled here; the other spaces
+/// need `blitter_new` and its `BlitOptions`.
+fn entry_point(src_space: ColourSpace, dest_format: wgpu::TextureFormat) -> Result<&'static str> {
+    use wgpu::TextureFormat as F;
+    use ColourSpace as S;
+
+    Ok(match (src_space, dest_format) {
+        // FIXME use sRGB viewFormats instead once the API stabilises
+        (S::Linear, F::Bgra8Unorm | F::Rgba8Unorm) => "fs_main_linear_to_srgb",
+        // The format performs sRGB encoding.
+        (S::Linear, F::Bgra8UnormSrgb | F::Rgba8UnormSrgb) => "fs_main",
+        (S::Linear | S::ScRgb, F::Rgba16Float) => "fs_main",
+        (S::Rgbe, F::Rgba16Float) => "fs_main_rgbe_to_linear",
+        (S::Srgb, F::Bgra8UnormSrgb | F::Rgba8UnormSrgb | F::Rgba16Float) => {
+            "fs_main_srgb_to_linear"
+        }
+        _ => bail!(
+            "blitter_old can't convert {src_space:?} for a {dest_format:?} target; use blitter_new"
+        ),
+    })
 }
 
 pub struct Blitter {
@@ -11,15 +56,36 @@ pub struct Blitter {
 }
 
 impl Blitter {
+    /// Fails for conversions this blitter doesn't have a shader for, such as
+    /// wide-gamut or HDR-encoded sources, which only `blitter_new` decodes.
     pub fn new(
         device: &wgpu::Device,
         src: &wgpu::TextureView,
         src_space: ColourSpace,
         dest_format: wgpu::TextureFormat,
-    ) -> Self {
+    ) -> Result<Self> {
+        Self::with_shader(
+            device,
+            src,
+            src_space,
+            dest_format,
+            include_str!("blit_old.wgsl"),
+        )
+    }
+
+    /// Like [`Self::new`] with `blit_old.wgsl` source from elsewhere, e.g.
+    /// reloaded from disk.
+    pub fn with_shader(
+        device: &wgpu::Device,
+        src: &wgpu::TextureView,
+        src_space: ColourSpace,
+        dest_format: wgpu::TextureFormat,
+        shader_source: &str,
+    ) -> Result<Self> {
+        let entry_point = entry_point(src_space, dest_format)?;
         let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("blit_old.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
         });
         let render_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -43,26 +109,36 @@ impl Blitter {
                     },
                 ],
             });
-        Blitter {
+        Ok(Blitter {
             render_bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: None,
                 layout: &render_bind_group_layout,
                 entries: &[
-                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(src) },
-                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&device.create_sampler(&wgpu::SamplerDescriptor {
-                        min_filter: wgpu::FilterMode::Linear,
-                        mag_filter: wgpu::FilterMode::Linear,
-                        ..Default::default()
-                    })) },
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(src),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&device.create_sampler(
+                            &wgpu::SamplerDescriptor {
+                                min_filter: wgpu::FilterMode::Linear,
+                                mag_filter: wgpu::FilterMode::Linear,
+                                ..Default::default()
+                            },
+                        )),
+                    },
                 ],
             }),
             render_pipeline: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                 label: None,
-                layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: None,
-                    bind_group_layouts: &[&render_bind_group_layout],
-                    push_constant_ranges: &[],
-                })),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: None,
+                        bind_group_layouts: &[&render_bind_group_layout],
+                        push_constant_ranges: &[],
+                    }),
+                ),
                 vertex: wgpu::VertexState {
                     module: &render_shader,
                     entry_point: "vs_main",
@@ -70,16 +146,7 @@ impl Blitter {
                 },
                 fragment: Some(wgpu::FragmentState {
                     module: &render_shader,
-                    entry_point: match (src_space, dest_format) {
-                        // FIXME use sRGB viewFormats instead once the API stabilises
-                        (ColourSpace::Linear, wgpu::TextureFormat::Bgra8Unorm) => "fs_main_linear_to_srgb",
-                        (ColourSpace::Linear, wgpu::TextureFormat::Rgba8Unorm) => "fs_main_linear_to_srgb",
-                        (ColourSpace::Linear, wgpu::TextureFormat::Bgra8UnormSrgb) => "fs_main", // format automatically performs sRGB encoding
-                        (ColourSpace::Linear, wgpu::TextureFormat::Rgba8UnormSrgb) => "fs_main",
-                        (ColourSpace::Linear, wgpu::TextureFormat::Rgba16Float) => "fs_main",
-                        (ColourSpace::Rgbe, wgpu::TextureFormat::Rgba16Float) => "fs_main_rgbe_to_linear",
-                        _ => panic!("Blitter: unrecognised conversion from {src_space:?} to {dest_format:?}")
-                    },
+                    entry_point,
                     targets: &[Some(dest_format.into())],
                 }),
                 primitive: wgpu::PrimitiveState::default(),
@@ -88,7 +155,42 @@ impl Blitter {
                 multiview: None,
             }),
             dest_format,
+        })
+    }
+
+    pub fn from_subresource(
+        device: &wgpu::Device,
+        src: &wgpu::Texture,
+        src_sub: Subresource,
+        src_space: ColourSpace,
+        dest_format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        if src.dimension() == wgpu::TextureDimension::D3 {
+            bail!("blitter_old can't blit 3D sources; use blitter_new");
         }
+        Self::new(
+            device,
+            &src_sub.source_view(src, src.format()),
+            src_space,
+            dest_format,
+        )
+    }
+
+    pub fn blit_to_subresource(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        dst: &wgpu::Texture,
+        dst_sub: Subresource,
+    ) {
+        let extent = dst_sub.extent(dst);
+        dst_sub.render_into(device, encoder, dst, self.dest_format, |encoder, view| {
+            self.blit_with_viewport(
+                encoder,
+                view,
+                (0., 0., extent.width as f32, extent.height as f32),
+            )
+        });
     }
 
     pub fn blit_with_viewport(
@@ -115,3 +217,62 @@ impl Blitter {
         render_pass.draw(0..3, 0..1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_spaces_are_errors() {
+        use ColourSpace as S;
+
+        let formats = [
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureFormat::Bgra8UnormSrgb,
+            wgpu::TextureFormat::Rgba16Float,
+        ];
+        for space in [S::DisplayP3, S::Rec2020, S::Pq, S::Hlg, S::AdobeRgb] {
+            for format in formats {
+                assert!(
+                    entry_point(space, format).is_err(),
+                    "{space:?} to {format:?}"
+                );
+            }
+        }
+        assert_eq!(
+            entry_point(S::Rgbe, wgpu::TextureFormat::Rgba16Float).unwrap(),
+            "fs_main_rgbe_to_linear"
+        );
+        assert!(entry_point(S::Rgbe, wgpu::TextureFormat::Rgba8Unorm).is_err());
+    }
+
+    #[test]
+    fn volume_subresources_are_errors() {
+        let Some((device, _)) = crate::golden::headless_device() else {
+            eprintln!("No adapter, skipping the 3D subresource");
+            return;
+        };
+        let volume = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth_or_array_layers: 4,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let blitter = Blitter::from_subresource(
+            &device,
+            &volume,
+            Subresource::new(0, 0),
+            ColourSpace::Linear,
+            wgpu::TextureFormat::Rgba8Unorm,
+        );
+        assert!(blitter.is_err());
+    }
+}
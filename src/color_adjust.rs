@@ -0,0 +1,121 @@
+/// Affine colour transform `matrix * rgba + offset`, applied to linear values.
+/// `matrix` is column-major, matching WGSL's `mat4x4`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ColorAdjust {
+    pub matrix: [[f32; 4]; 4],
+    pub offset: [f32; 4],
+}
+
+const REC709_LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+const MIDDLE_GREY: f32 = 0.18;
+
+impl Default for ColorAdjust {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl ColorAdjust {
+    pub const IDENTITY: Self = Self {
+        matrix: [
+            [1., 0., 0., 0.],
+            [0., 1., 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ],
+        offset: [0.; 4],
+    };
+
+    /// Builds from a row-major RGB matrix, leaving alpha alone.
+    fn from_rgb_rows(rows: [[f32; 3]; 3], offset: [f32; 3]) -> Self {
+        let mut matrix = Self::IDENTITY.matrix;
+        for (r, row) in rows.iter().enumerate() {
+            for (c, value) in row.iter().enumerate() {
+                matrix[c][r] = *value;
+            }
+        }
+        Self {
+            matrix,
+            offset: [offset[0], offset[1], offset[2], 0.],
+        }
+    }
+
+    pub fn brightness(amount: f32) -> Self {
+        Self {
+            offset: [amount, amount, amount, 0.],
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Scales distance from linear middle grey.
+    pub fn contrast(amount: f32) -> Self {
+        let pivot = MIDDLE_GREY * (1. - amount);
+        Self::from_rgb_rows(
+            [[amount, 0., 0.], [0., amount, 0.], [0., 0., amount]],
+            [pivot; 3],
+        )
+    }
+
+    /// 0 is grayscale, 1 is unchanged, above 1 oversaturates.
+    pub fn saturation(amount: f32) -> Self {
+        let [lr, lg, lb] = REC709_LUMA.map(|l| l * (1. - amount));
+        Self::from_rgb_rows(
+            [
+                [lr + amount, lg, lb],
+                [lr, lg + amount, lb],
+                [lr, lg, lb + amount],
+            ],
+            [0.; 3],
+        )
+    }
+
+    /// Rotates hue around the luma axis, as in the CSS `hue-rotate` filter.
+    pub fn hue_rotate(radians: f32) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Self::from_rgb_rows(
+            [
+                [
+                    0.213 + cos * 0.787 - sin * 0.213,
+                    0.715 - cos * 0.715 - sin * 0.715,
+                    0.072 - cos * 0.072 + sin * 0.928,
+                ],
+                [
+                    0.213 - cos * 0.213 + sin * 0.143,
+                    0.715 + cos * 0.285 + sin * 0.140,
+                    0.072 - cos * 0.072 - sin * 0.283,
+                ],
+                [
+                    0.213 - cos * 0.213 - sin * 0.787,
+                    0.715 - cos * 0.715 + sin * 0.715,
+                    0.072 + cos * 0.928 + sin * 0.072,
+                ],
+            ],
+            [0.; 3],
+        )
+    }
+
+    pub fn invert() -> Self {
+        Self::from_rgb_rows([[-1., 0., 0.], [0., -1., 0.], [0., 0., -1.]], [1.; 3])
+    }
+
+    pub fn grayscale() -> Self {
+        Self::saturation(0.)
+    }
+
+    /// Applies `self` first and `next` after it.
+    pub fn then(&self, next: &Self) -> Self {
+        let mut matrix = [[0.; 4]; 4];
+        let mut offset = next.offset;
+        for (c, column) in matrix.iter_mut().enumerate() {
+            for (r, value) in column.iter_mut().enumerate() {
+                *value = (0..4).map(|k| next.matrix[k][r] * self.matrix[c][k]).sum();
+            }
+        }
+        for (r, value) in offset.iter_mut().enumerate() {
+            *value += (0..4)
+                .map(|k| next.matrix[k][r] * self.offset[k])
+                .sum::<f32>();
+        }
+        Self { matrix, offset }
+    }
+}
@@ -0,0 +1,549 @@
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use wgpu::util::DeviceExt;
+
+use crate::bc;
+
+/// A block-compressed texture read from a KTX2 or DDS container, with every
+/// mip level and array layer.
+#[derive(Clone, Debug)]
+pub struct CompressedTexture {
+    pub width: u32,
+    pub height: u32,
+    pub mip_level_count: u32,
+    /// Array layers, six per cube.
+    pub layers: u32,
+    pub cube: bool,
+    /// Already `*Srgb` when the container says the data is sRGB-encoded.
+    pub format: wgpu::TextureFormat,
+    /// Layer-major, then mip, as `create_texture_with_data` expects.
+    pub data: Vec<u8>,
+}
+
+/// Byte size of one `width` x `height` image of `format`.
+fn image_size(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+    let (block_width, block_height) = format.block_dimensions();
+    let blocks_x = width.div_ceil(block_width) as usize;
+    let blocks_y = height.div_ceil(block_height) as usize;
+    blocks_x * blocks_y * format.block_size(None).unwrap() as usize
+}
+
+impl CompressedTexture {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("ktx2") => Self::from_ktx2(&bytes),
+            Some("dds") => Self::from_dds(&bytes),
+            _ => bail!("{} is neither KTX2 nor DDS", path.display()),
+        }
+        .with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self> {
+        use ktx2::Format as K;
+        use wgpu::TextureFormat as F;
+
+        let reader = ktx2::Reader::new(bytes).context("Invalid KTX2 container")?;
+        let header = reader.header();
+        if let Some(scheme) = header.supercompression_scheme {
+            bail!("Supercompressed KTX2 ({scheme:?}) is not supported");
+        }
+        ensure!(
+            header.pixel_depth <= 1,
+            "3D KTX2 textures are not supported"
+        );
+
+        let format = match header.format {
+            Some(K::BC1_RGB_UNORM_BLOCK | K::BC1_RGBA_UNORM_BLOCK) => F::Bc1RgbaUnorm,
+            Some(K::BC1_RGB_SRGB_BLOCK | K::BC1_RGBA_SRGB_BLOCK) => F::Bc1RgbaUnormSrgb,
+            Some(K::BC2_UNORM_BLOCK) => F::Bc2RgbaUnorm,
+            Some(K::BC2_SRGB_BLOCK) => F::Bc2RgbaUnormSrgb,
+            Some(K::BC3_UNORM_BLOCK) => F::Bc3RgbaUnorm,
+            Some(K::BC3_SRGB_BLOCK) => F::Bc3RgbaUnormSrgb,
+            Some(K::BC4_UNORM_BLOCK) => F::Bc4RUnorm,
+            Some(K::BC4_SNORM_BLOCK) => F::Bc4RSnorm,
+            Some(K::BC5_UNORM_BLOCK) => F::Bc5RgUnorm,
+            Some(K::BC5_SNORM_BLOCK) => F::Bc5RgSnorm,
+            Some(K::BC6H_UFLOAT_BLOCK) => F::Bc6hRgbUfloat,
+            Some(K::BC6H_SFLOAT_BLOCK) => F::Bc6hRgbFloat,
+            Some(K::BC7_UNORM_BLOCK) => F::Bc7RgbaUnorm,
+            Some(K::BC7_SRGB_BLOCK) => F::Bc7RgbaUnormSrgb,
+            format => bail!("Unsupported KTX2 format {format:?}"),
+        };
+        // The DFD transfer function is the authoritative colour metadata;
+        // fall back to the vkFormat when it's missing.
+        let srgb = reader
+            .data_format_descriptors()
+            .find(|dfd| dfd.header.vendor_id == 0 && dfd.header.descriptor_type == 0)
+            .and_then(|dfd| ktx2::BasicDataFormatDescriptor::parse(dfd.data).ok())
+            .and_then(|basic| basic.transfer_function)
+            .map_or(format.is_srgb(), |transfer| {
+                transfer == ktx2::TransferFunction::SRGB
+            });
+
+        let faces = header.face_count;
+        let layers = header.layer_count.max(1) * faces;
+        let levels: Vec<&[u8]> = reader.levels().collect();
+        let mut data = Vec::with_capacity(levels.iter().map(|level| level.len()).sum());
+        // KTX2 stores each level's layers together; wgpu wants each layer's
+        // levels together.
+        for layer in 0..layers as usize {
+            for level in &levels {
+                let image_size = level.len() / layers as usize;
+                data.extend_from_slice(&level[layer * image_size..][..image_size]);
+            }
+        }
+
+        Self {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            mip_level_count: levels.len() as u32,
+            layers,
+            cube: faces == 6,
+            format: with_srgb(format, srgb),
+            data,
+        }
+        .validated()
+    }
+
+    pub fn from_dds(bytes: &[u8]) -> Result<Self> {
+        use ddsfile::{DxgiFormat as D, FourCC};
+        use wgpu::TextureFormat as F;
+
+        let dds = ddsfile::Dds::read(bytes).context("Invalid DDS container")?;
+        // Not `get_dxgi_format`, which guesses sRGB for legacy DXT FourCCs.
+        let format = match dds.header10.as_ref().map(|header10| header10.dxgi_format) {
+            Some(D::BC1_Typeless | D::BC1_UNorm) => F::Bc1RgbaUnorm,
+            Some(D::BC1_UNorm_sRGB) => F::Bc1RgbaUnormSrgb,
+            Some(D::BC2_Typeless | D::BC2_UNorm) => F::Bc2RgbaUnorm,
+            Some(D::BC2_UNorm_sRGB) => F::Bc2RgbaUnormSrgb,
+            Some(D::BC3_Typeless | D::BC3_UNorm) => F::Bc3RgbaUnorm,
+            Some(D::BC3_UNorm_sRGB) => F::Bc3RgbaUnormSrgb,
+            Some(D::BC4_Typeless | D::BC4_UNorm) => F::Bc4RUnorm,
+            Some(D::BC4_SNorm) => F::Bc4RSnorm,
+            Some(D::BC5_Typeless | D::BC5_UNorm) => F::Bc5RgUnorm,
+            Some(D::BC5_SNorm) => F::Bc5RgSnorm,
+            Some(D::BC6H_Typeless | D::BC6H_UF16) => F::Bc6hRgbUfloat,
+            Some(D::BC6H_SF16) => F::Bc6hRgbFloat,
+            Some(D::BC7_Typeless | D::BC7_UNorm) => F::Bc7RgbaUnorm,
+            Some(D::BC7_UNorm_sRGB) => F::Bc7RgbaUnormSrgb,
+            Some(format) => bail!("Unsupported DDS format {format:?}"),
+            // Legacy headers have no colour metadata, so these load as UNORM.
+            None => match dds.header.spf.fourcc.as_ref().map(|fourcc| fourcc.0) {
+                Some(FourCC::DXT1) => F::Bc1RgbaUnorm,
+                Some(FourCC::DXT2 | FourCC::DXT3) => F::Bc2RgbaUnorm,
+                Some(FourCC::DXT4 | FourCC::DXT5) => F::Bc3RgbaUnorm,
+                Some(FourCC::ATI1 | FourCC::BC4_UNORM) => F::Bc4RUnorm,
+                Some(FourCC::BC4_SNORM) => F::Bc4RSnorm,
+                Some(FourCC::ATI2) => F::Bc5RgUnorm,
+                Some(FourCC::BC5_SNORM) => F::Bc5RgSnorm,
+                fourcc => bail!("Unsupported DDS pixel format (FourCC {fourcc:?})"),
+            },
+        };
+        ensure!(dds.get_depth() <= 1, "3D DDS textures are not supported");
+
+        let cube = match &dds.header10 {
+            Some(header10) => header10.misc_flag.contains(ddsfile::MiscFlag::TEXTURECUBE),
+            None => dds.header.caps2.contains(ddsfile::Caps2::CUBEMAP),
+        };
+        let layers = match &dds.header10 {
+            Some(header10) => header10.array_size.max(1) * if cube { 6 } else { 1 },
+            None if cube => 6,
+            None => 1,
+        };
+
+        Self {
+            width: dds.get_width(),
+            height: dds.get_height(),
+            mip_level_count: dds.get_num_mipmap_levels().max(1),
+            layers,
+            cube,
+            format,
+            data: dds.data,
+        }
+        .validated()
+    }
+
+    fn mip_size(&self, mip: u32) -> (u32, u32) {
+        ((self.width >> mip).max(1), (self.height >> mip).max(1))
+    }
+
+    /// Bytes of one layer's mip chain.
+    fn layer_size(&self) -> usize {
+        (0..self.mip_level_count)
+            .map(|mip| {
+                let (width, height) = self.mip_size(mip);
+                image_size(self.format, width, height)
+            })
+            .sum()
+    }
+
+    /// Decodes every level and layer on the CPU; see [`bc::decoded_format`]
+    /// for the resulting format. Returns a clone if already uncompressed.
+    pub fn decompress(&self) -> Self {
+        let Some(format) = bc::decoded_format(self.format) else {
+            return self.clone();
+        };
+        let mut data = Vec::new();
+        let mut offset = 0;
+        for _ in 0..self.layers {
+            for mip in 0..self.mip_level_count {
+                let (width, height) = self.mip_size(mip);
+                let size = image_size(self.format, width, height);
+                data.extend(bc::decode(
+                    self.format,
+                    width,
+                    height,
+                    &self.data[offset..offset + size],
+                ));
+                offset += size;
+            }
+        }
+        Self {
+            format,
+            data,
+            ..*self
+        }
+    }
+
+    fn validated(mut self) -> Result<Self> {
+        ensure!(self.width > 0 && self.height > 0, "Empty texture");
+        ensure!(
+            self.mip_level_count <= 32 - self.width.max(self.height).leading_zeros(),
+            "{} mip levels is too many for {}x{}",
+            self.mip_level_count,
+            self.width,
+            self.height
+        );
+        let expected = self.layer_size() * self.layers as usize;
+        ensure!(
+            self.data.len() >= expected,
+            "Expected {expected} bytes of texel data, found {}",
+            self.data.len()
+        );
+        self.data.truncate(expected);
+        Ok(self)
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: self.layers,
+        }
+    }
+
+    /// Uploads every level and layer. The texture can be viewed as both the
+    /// sRGB and non-sRGB variant of its format. Without
+    /// `Features::TEXTURE_COMPRESSION_BC` the data is decoded on the CPU
+    /// first, so check the returned texture's format.
+    pub fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
+        if self.format.is_compressed()
+            && !device
+                .features()
+                .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+        {
+            let decoded = self.decompress();
+            log::info!(
+                "TEXTURE_COMPRESSION_BC is unavailable, decoded {} from {:?} to {:?} on the CPU",
+                label.unwrap_or("texture"),
+                self.format,
+                decoded.format
+            );
+            return decoded.create_texture(device, queue, label, usage);
+        }
+        device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label,
+                size: self.size(),
+                mip_level_count: self.mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage,
+                view_formats: &[
+                    self.format.add_srgb_suffix(),
+                    self.format.remove_srgb_suffix(),
+                ],
+            },
+            &self.data,
+        )
+    }
+}
+
+fn with_srgb(format: wgpu::TextureFormat, srgb: bool) -> wgpu::TextureFormat {
+    if srgb {
+        format.add_srgb_suffix()
+    } else {
+        format.remove_srgb_suffix()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wgpu::TextureFormat as F;
+
+    /// One image of `format` filled with `(layer << 4) | mip`, so the byte
+    /// says which subresource it came from.
+    fn marked(format: F, (width, height): (u32, u32), layer: u32, mip: u32) -> Vec<u8> {
+        let size = image_size(format, (width >> mip).max(1), (height >> mip).max(1));
+        vec![(layer << 4 | mip) as u8; size]
+    }
+
+    /// Marker bytes of `texture`'s data, one per subresource, in order.
+    fn markers(texture: &CompressedTexture) -> Vec<(usize, u8)> {
+        let mut offset = 0;
+        let mut result = Vec::new();
+        for _ in 0..texture.layers {
+            for mip in 0..texture.mip_level_count {
+                let (width, height) = texture.mip_size(mip);
+                let size = image_size(texture.format, width, height);
+                let image = &texture.data[offset..offset + size];
+                assert!(image.iter().all(|&byte| byte == image[0]));
+                result.push((offset, image[0]));
+                offset += size;
+            }
+        }
+        assert_eq!(offset, texture.data.len());
+        result
+    }
+
+    /// A KTX2 file with level data stored smallest first, as the spec lays
+    /// it out. `transfer` adds a basic DFD with that transfer function.
+    fn ktx2(
+        vk_format: u32,
+        (width, height): (u32, u32),
+        (layer_count, faces, levels): (u32, u32, u32),
+        transfer: Option<u8>,
+    ) -> Vec<u8> {
+        let format = fixture_format(vk_format);
+        let layers = layer_count.max(1) * faces;
+        let level_data: Vec<Vec<u8>> = (0..levels)
+            .map(|mip| {
+                (0..layers)
+                    .flat_map(|layer| marked(format, (width, height), layer, mip))
+                    .collect()
+            })
+            .collect();
+
+        let index_end = 80 + 24 * levels as usize;
+        let dfd: Vec<u8> = match transfer {
+            Some(transfer) => [
+                &28u32.to_le_bytes()[..],
+                &0u32.to_le_bytes(),
+                &(2u32 | 24 << 16).to_le_bytes(),
+                &[1, 1, transfer, 0],
+                &[0; 12],
+            ]
+            .concat(),
+            None => 4u32.to_le_bytes().to_vec(),
+        };
+        let mut offsets = vec![0; levels as usize];
+        let mut offset = index_end + dfd.len();
+        for mip in (0..levels as usize).rev() {
+            offsets[mip] = offset;
+            offset += level_data[mip].len();
+        }
+
+        let mut bytes = vec![
+            0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+        ];
+        for value in [
+            vk_format,
+            1,
+            width,
+            height,
+            0,
+            layer_count,
+            faces,
+            levels,
+            0,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+        for value in [index_end as u32, dfd.len() as u32, 0, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend([0; 16]);
+        for (mip, data) in level_data.iter().enumerate() {
+            for value in [offsets[mip], data.len(), data.len()] {
+                bytes.extend((value as u64).to_le_bytes());
+            }
+        }
+        bytes.extend(dfd);
+        for data in level_data.iter().rev() {
+            bytes.extend(data);
+        }
+        bytes
+    }
+
+    /// The handful of vkFormats the fixtures use.
+    fn fixture_format(vk_format: u32) -> F {
+        match vk_format {
+            BC1_UNORM => F::Bc1RgbaUnorm,
+            BC1_SRGB => F::Bc1RgbaUnormSrgb,
+            BC4_UNORM => F::Bc4RUnorm,
+            _ => unreachable!("No fixture format for vkFormat {vk_format}"),
+        }
+    }
+
+    const BC1_UNORM: u32 = 133;
+    const BC1_SRGB: u32 = 134;
+    const BC4_UNORM: u32 = 139;
+
+    /// Offsets and markers of `layers` mip chains with images of `sizes`
+    /// bytes, layer-major.
+    fn expected_markers(sizes: &[usize], layers: u32) -> Vec<(usize, u8)> {
+        let mut offset = 0;
+        let mut result = Vec::new();
+        for layer in 0..layers {
+            for (mip, size) in sizes.iter().enumerate() {
+                result.push((offset, (layer << 4 | mip as u32) as u8));
+                offset += size;
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn ktx2_levels_are_reordered_by_layer() {
+        let bytes = ktx2(BC4_UNORM, (16, 8), (2, 1, 3), None);
+        let texture = CompressedTexture::from_ktx2(&bytes).unwrap();
+        assert_eq!(texture.format, F::Bc4RUnorm);
+        assert_eq!((texture.width, texture.height), (16, 8));
+        assert_eq!((texture.mip_level_count, texture.layers), (3, 2));
+        assert!(!texture.cube);
+        // 4x2, 2x1 and 1x1 blocks of 8 bytes.
+        assert_eq!(markers(&texture), expected_markers(&[64, 16, 8], 2));
+    }
+
+    #[test]
+    fn ktx2_cubes_have_six_layers() {
+        let bytes = ktx2(BC1_UNORM, (4, 4), (0, 6, 1), None);
+        let texture = CompressedTexture::from_ktx2(&bytes).unwrap();
+        assert!(texture.cube);
+        assert_eq!((texture.mip_level_count, texture.layers), (1, 6));
+        assert_eq!(markers(&texture), expected_markers(&[8], 6));
+    }
+
+    #[test]
+    fn ktx2_srgb_follows_the_dfd() {
+        let format = |vk_format, transfer| {
+            let bytes = ktx2(vk_format, (4, 4), (0, 1, 1), transfer);
+            CompressedTexture::from_ktx2(&bytes).unwrap().format
+        };
+        // KHR_DF_TRANSFER_LINEAR is 1, KHR_DF_TRANSFER_SRGB is 2.
+        assert_eq!(format(BC1_UNORM, Some(2)), F::Bc1RgbaUnormSrgb);
+        assert_eq!(format(BC1_SRGB, Some(1)), F::Bc1RgbaUnorm);
+        assert_eq!(format(BC1_UNORM, None), F::Bc1RgbaUnorm);
+        assert_eq!(format(BC1_SRGB, None), F::Bc1RgbaUnormSrgb);
+    }
+
+    #[test]
+    fn ktx2_rejects_too_many_levels() {
+        let bytes = ktx2(BC1_UNORM, (4, 4), (0, 1, 4), None);
+        let error = CompressedTexture::from_ktx2(&bytes).unwrap_err();
+        assert_eq!(error.to_string(), "4 mip levels is too many for 4x4");
+    }
+
+    /// A DDS file whose data is marked like [`ktx2`]'s; DDS already stores
+    /// each layer's mip chain together.
+    fn dds(dds: ddsfile::Dds, format: F) -> Vec<u8> {
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let layers = dds.get_num_array_layers().max(1)
+            * if dds.header.caps2.contains(ddsfile::Caps2::CUBEMAP) {
+                6
+            } else {
+                1
+            };
+        let mut dds = dds;
+        dds.data = (0..layers)
+            .flat_map(|layer| {
+                (0..dds.get_num_mipmap_levels())
+                    .flat_map(move |mip| marked(format, (width, height), layer, mip))
+            })
+            .collect();
+        let mut bytes = Vec::new();
+        dds.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn dxgi(format: ddsfile::DxgiFormat, size: (u32, u32), mips: u32, layers: u32) -> ddsfile::Dds {
+        ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height: size.1,
+            width: size.0,
+            depth: None,
+            format,
+            mipmap_levels: Some(mips),
+            array_layers: Some(layers),
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+            alpha_mode: ddsfile::AlphaMode::Unknown,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn dds_array_keeps_layer_order() {
+        let bytes = dds(
+            dxgi(ddsfile::DxgiFormat::BC7_UNorm_sRGB, (8, 8), 4, 3),
+            F::Bc7RgbaUnormSrgb,
+        );
+        let texture = CompressedTexture::from_dds(&bytes).unwrap();
+        assert_eq!(texture.format, F::Bc7RgbaUnormSrgb);
+        assert_eq!((texture.mip_level_count, texture.layers), (4, 3));
+        assert!(!texture.cube);
+        // 2x2, then 1x1 blocks of 16 bytes.
+        assert_eq!(markers(&texture), expected_markers(&[64, 16, 16, 16], 3));
+    }
+
+    #[test]
+    fn dds_legacy_fourcc_is_unorm() {
+        let legacy = ddsfile::Dds::new_d3d(ddsfile::NewD3dParams {
+            height: 8,
+            width: 4,
+            depth: None,
+            format: ddsfile::D3DFormat::DXT1,
+            mipmap_levels: Some(2),
+            caps2: None,
+        })
+        .unwrap();
+        let bytes = dds(legacy, F::Bc1RgbaUnorm);
+        let texture = CompressedTexture::from_dds(&bytes).unwrap();
+        assert_eq!(texture.format, F::Bc1RgbaUnorm);
+        assert_eq!((texture.width, texture.height), (4, 8));
+        assert_eq!((texture.mip_level_count, texture.layers), (2, 1));
+        assert_eq!(markers(&texture), expected_markers(&[16, 8], 1));
+    }
+
+    #[test]
+    fn dds_rejects_short_data() {
+        let mut bytes = dds(
+            dxgi(ddsfile::DxgiFormat::BC4_UNorm, (8, 8), 1, 1),
+            F::Bc4RUnorm,
+        );
+        bytes.pop();
+        let error = CompressedTexture::from_dds(&bytes).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected 32 bytes of texel data, found 31"
+        );
+    }
+}
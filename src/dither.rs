@@ -0,0 +1,127 @@
+use wgpu::util::DeviceExt;
+
+pub const BLUE_NOISE_SIZE: u32 = 64;
+
+/// Blue-noise threshold map built with Ulichney's void-and-cluster method on
+/// a torus, so it tiles seamlessly. Values are ranks scaled to `0..=255`.
+pub fn blue_noise(size: u32) -> Vec<u8> {
+    let size = size as usize;
+    let count = size * size;
+    let sigma = 1.5f32;
+
+    let mut kernel = vec![0f32; count];
+    for y in 0..size {
+        for x in 0..size {
+            let dx = x.min(size - x) as f32;
+            let dy = y.min(size - y) as f32;
+            kernel[y * size + x] = (-(dx * dx + dy * dy) / (2. * sigma * sigma)).exp();
+        }
+    }
+    let update = |energy: &mut [f32], index: usize, sign: f32| {
+        let (px, py) = (index % size, index / size);
+        for y in 0..size {
+            let ky = (y + size - py) % size;
+            for x in 0..size {
+                let kx = (x + size - px) % size;
+                energy[y * size + x] += sign * kernel[ky * size + kx];
+            }
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f32]| {
+        (0..count)
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[f32]| {
+        (0..count)
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // Deterministic random initial pattern with ~10% of the pixels set.
+    let mut state = 0x853c49e6748fea9bu64;
+    let mut pattern = vec![false; count];
+    let mut energy = vec![0f32; count];
+    let initial = count / 10;
+    let mut placed = 0;
+    while placed < initial {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let index = (state >> 33) as usize % count;
+        if !pattern[index] {
+            pattern[index] = true;
+            update(&mut energy, index, 1.);
+            placed += 1;
+        }
+    }
+
+    // Spread the initial points out until they settle.
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.);
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        update(&mut energy, void, 1.);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0usize; count];
+    {
+        let mut pattern = pattern.clone();
+        let mut energy = energy.clone();
+        for rank in (0..initial).rev() {
+            let cluster = tightest_cluster(&pattern, &energy);
+            pattern[cluster] = false;
+            update(&mut energy, cluster, -1.);
+            ranks[cluster] = rank;
+        }
+    }
+    for rank in initial..count {
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        update(&mut energy, void, 1.);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank * 256 / count) as u8)
+        .collect()
+}
+
+/// Bytes in the blue-noise uniform, one per texel.
+pub const BLUE_NOISE_BYTES: u64 = (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as u64;
+
+/// The blue-noise map as a uniform buffer of packed bytes. Its contents are
+/// set at creation, so blits never depend on an upload having been
+/// submitted first.
+pub fn create_blue_noise_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Blue Noise"),
+        contents: &blue_noise(BLUE_NOISE_SIZE),
+        usage: wgpu::BufferUsages::UNIFORM,
+    })
+}
+
+/// Size of one quantisation step of `format`, or 0 when dithering makes no
+/// sense for it.
+pub fn quantisation_step(format: wgpu::TextureFormat) -> f32 {
+    use wgpu::TextureFormat as F;
+    match format {
+        F::R8Unorm
+        | F::Rg8Unorm
+        | F::Rgba8Unorm
+        | F::Rgba8UnormSrgb
+        | F::Bgra8Unorm
+        | F::Bgra8UnormSrgb => 1. / 255.,
+        F::Rgb10a2Unorm => 1. / 1023.,
+        F::R16Unorm | F::Rg16Unorm | F::Rgba16Unorm => 1. / 65535.,
+        _ => 0.,
+    }
+}
@@ -0,0 +1,288 @@
+//! Golden-image snapshots. Renders are compared with PNGs on disk by maximum
+//! error, PSNR and SSIM; a failing render is written next to its golden with
+//! a diff image. Setting `UPDATE_GOLDEN=1` rewrites the goldens instead.
+
+use std::{
+    path::Path,
+    sync::{mpsc, OnceLock},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use pollster::FutureExt;
+
+use crate::reference::{self, Image};
+
+pub const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+
+/// SSIM window size; windows are clipped at the image edges.
+const SSIM_WINDOW: u32 = 8;
+
+/// How close a render is to its golden. Values are compared as stored, e.g.
+/// sRGB-encoded for `*Srgb` targets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Metrics {
+    pub max_abs: f32,
+    /// In dB over all four channels, infinite for identical images.
+    pub psnr: f32,
+    /// Mean structural similarity of luma, 1 for identical images.
+    pub ssim: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tolerance {
+    pub max_abs: f32,
+    pub min_psnr: f32,
+    pub min_ssim: f32,
+}
+
+impl Default for Tolerance {
+    /// Allows the odd rounding difference between GPUs.
+    fn default() -> Self {
+        Self {
+            max_abs: 2. / 255.,
+            min_psnr: 40.,
+            min_ssim: 0.99,
+        }
+    }
+}
+
+impl Tolerance {
+    pub fn accepts(&self, metrics: &Metrics) -> bool {
+        metrics.max_abs <= self.max_abs
+            && metrics.psnr >= self.min_psnr
+            && metrics.ssim >= self.min_ssim
+    }
+}
+
+pub fn metrics(expected: &Image, actual: &Image) -> Metrics {
+    Metrics {
+        max_abs: reference::difference(expected, actual).max_abs,
+        psnr: psnr(expected, actual),
+        ssim: ssim(expected, actual),
+    }
+}
+
+/// Peak signal-to-noise ratio for values in [0, 1].
+pub fn psnr(expected: &Image, actual: &Image) -> f32 {
+    let squared: f64 = expected
+        .texels
+        .iter()
+        .zip(&actual.texels)
+        .flat_map(|(e, a)| (0..4).map(move |c| ((e[c] - a[c]) as f64).powi(2)))
+        .sum();
+    let mse = squared / (expected.texels.len() * 4).max(1) as f64;
+    (-10. * mse.log10()) as f32
+}
+
+fn luma([r, g, b, _]: [f32; 4]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Structural similarity of luma, averaged over windows overlapping by half.
+pub fn ssim(expected: &Image, actual: &Image) -> f32 {
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height),
+        "Images differ in size"
+    );
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    let starts = |size: u32| {
+        let step = SSIM_WINDOW / 2;
+        (0..size.saturating_sub(SSIM_WINDOW) + step)
+            .step_by(step as usize)
+            .map(move |start| (start, (start + SSIM_WINDOW).min(size)))
+    };
+    let (mut total, mut windows) = (0., 0);
+    for (y0, y1) in starts(expected.height) {
+        for (x0, x1) in starts(expected.width) {
+            let pairs: Vec<_> = (y0..y1)
+                .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    (
+                        luma(expected.texel(x, y)) as f64,
+                        luma(actual.texel(x, y)) as f64,
+                    )
+                })
+                .collect();
+            let n = pairs.len() as f64;
+            let mean_e = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+            let mean_a = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+            let (mut var_e, mut var_a, mut cov) = (0., 0., 0.);
+            for (e, a) in &pairs {
+                var_e += (e - mean_e).powi(2) / n;
+                var_a += (a - mean_a).powi(2) / n;
+                cov += (e - mean_e) * (a - mean_a) / n;
+            }
+            total += (2. * mean_e * mean_a + C1) * (2. * cov + C2)
+                / ((mean_e.powi(2) + mean_a.powi(2) + C1) * (var_e + var_a + C2));
+            windows += 1;
+        }
+    }
+    (total / windows.max(1) as f64) as f32
+}
+
+/// A dimmed copy of `expected` with differences in red, amplified so a
+/// single 8-bit step shows.
+pub fn diff_image(expected: &Image, actual: &Image) -> Image {
+    Image::from_fn(expected.width, expected.height, |x, y| {
+        let (e, a) = (expected.texel(x, y), actual.texel(x, y));
+        let error = (0..4).map(|c| (e[c] - a[c]).abs()).fold(0., f32::max);
+        let base = luma(e).clamp(0., 1.) * 0.3;
+        [(base + error * 32.).min(1.), base, base, 1.]
+    })
+}
+
+fn load_png(path: &Path) -> Result<Image> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(Image::from_rgba8(width, height, &image, false))
+}
+
+fn save_png(path: &Path, image: &Image) -> Result<()> {
+    image::save_buffer(
+        path,
+        &image.to_rgba8(false),
+        image.width,
+        image.height,
+        image::ColorType::Rgba8,
+    )
+    .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn updating() -> bool {
+    std::env::var_os(UPDATE_ENV).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Compares `actual` with `dir/name.png`. On failure `name.actual.png` and
+/// `name.diff.png` are written alongside. With [`UPDATE_ENV`] set the golden
+/// is replaced and always passes.
+pub fn check(dir: &Path, name: &str, actual: &Image, tolerance: Tolerance) -> Result<Metrics> {
+    let golden = dir.join(format!("{name}.png"));
+    if updating() {
+        std::fs::create_dir_all(dir)?;
+        save_png(&golden, actual)?;
+        log::info!("Updated {}", golden.display());
+        return Ok(metrics(actual, actual));
+    }
+    if !golden.exists() {
+        bail!(
+            "{} is missing; run with {UPDATE_ENV}=1 to create it",
+            golden.display()
+        );
+    }
+    let expected = load_png(&golden)?;
+    ensure!(
+        (expected.width, expected.height) == (actual.width, actual.height),
+        "{name}: rendered {}x{}, golden is {}x{}",
+        actual.width,
+        actual.height,
+        expected.width,
+        expected.height
+    );
+    let metrics = metrics(&expected, actual);
+    if !tolerance.accepts(&metrics) {
+        save_png(&dir.join(format!("{name}.actual.png")), actual)?;
+        save_png(
+            &dir.join(format!("{name}.diff.png")),
+            &diff_image(&expected, actual),
+        )?;
+        bail!("{name}: {metrics:?} outside {tolerance:?}; see {name}.diff.png");
+    }
+    Ok(metrics)
+}
+
+/// A device without a surface, or `None` if there's no adapter, as on most
+/// CI machines.
+pub fn headless_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    headless_device_with_limits(wgpu::Limits::default())
+}
+
+/// Like [`headless_device`], e.g. with a small `max_texture_dimension_2d` to
+/// force tiling. Every device shares one instance, as GL instances share an
+/// EGL display and dropping one terminates it for the rest.
+pub fn headless_device_with_limits(limits: wgpu::Limits) -> Option<(wgpu::Device, wgpu::Queue)> {
+    static INSTANCE: OnceLock<wgpu::Instance> = OnceLock::new();
+    let adapter = INSTANCE
+        .get_or_init(wgpu::Instance::default)
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .block_on()?;
+    let descriptor = wgpu::DeviceDescriptor {
+        limits,
+        ..Default::default()
+    };
+    adapter.request_device(&descriptor, None).block_on().ok()
+}
+
+/// Renders into a fresh, transparent black `Rgba8Unorm` or `Rgba8UnormSrgb`
+/// target and reads it back as stored.
+pub fn render(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    (width, height): (u32, u32),
+    format: wgpu::TextureFormat,
+    draw: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+) -> Result<Image> {
+    ensure!(
+        format.remove_srgb_suffix() == wgpu::TextureFormat::Rgba8Unorm,
+        "Golden renders need an 8-bit RGBA target, not {format:?}"
+    );
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Golden Target"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let padded_row = wgpu::util::align_to(width * 4, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Golden Readback"),
+        size: (padded_row * height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    draw(&mut encoder, &texture.create_view(&Default::default()));
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &readback,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit([encoder.finish()]);
+
+    let slice = readback.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .context("Golden readback was dropped")?
+        .context("Failed to map golden readback")?;
+    let data: Vec<u8> = slice
+        .get_mapped_range()
+        .chunks(padded_row as usize)
+        .flat_map(|row| &row[..width as usize * 4])
+        .copied()
+        .collect();
+    readback.unmap();
+    Ok(Image::from_rgba8(width, height, &data, false))
+}
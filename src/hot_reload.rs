@@ -0,0 +1,91 @@
+//! Reloading WGSL from disk while the demo runs. Shaders are checked with naga
+//! and built inside an error scope, so a broken edit is logged instead of
+//! replacing working pipelines or panicking.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use notify::Watcher;
+use pollster::FutureExt;
+
+/// Parses and validates `source`, returning naga's diagnostics on failure.
+/// `name` labels the diagnostics.
+pub fn validate_wgsl(name: &str, source: &str) -> Result<()> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|error| anyhow!(error.emit_to_string_with_path(source, name)))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| anyhow!(error.emit_to_string_with_path(source, name)))?;
+    Ok(())
+}
+
+/// Reads and validates a WGSL file.
+pub fn load_wgsl(path: &Path) -> Result<String> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    validate_wgsl(&path.display().to_string(), &source)?;
+    Ok(source)
+}
+
+/// Runs `build` in a validation error scope, catching what naga can't see,
+/// such as a missing entry point or a binding that doesn't match the layout.
+pub fn try_build<T>(device: &wgpu::Device, build: impl FnOnce() -> T) -> Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let built = build();
+    match device.pop_error_scope().block_on() {
+        Some(error) => Err(anyhow!("{error}")),
+        None => Ok(built),
+    }
+}
+
+/// Watches a directory for `.wgsl` files being written.
+pub struct ShaderWatcher {
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+impl ShaderWatcher {
+    /// Watches the directory itself, as many editors save by replacing files.
+    pub fn new(dir: &Path) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher
+            .watch(dir, notify::RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Shaders created or modified since the last call, each listed once.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        let mut seen = HashSet::new();
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    log::warn!("Shader watcher error: {error}");
+                    continue;
+                }
+            };
+            if !(event.kind.is_create() || event.kind.is_modify()) {
+                continue;
+            }
+            for path in event.paths {
+                if path.extension().is_some_and(|ext| ext == "wgsl") && seen.insert(path.clone()) {
+                    changed.push(path);
+                }
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,221 @@
+//! Recognises the ICC profiles photos commonly embed, so they can be tagged
+//! with a [`ColourSpace`] instead of being colour managed in full.
+
+use crate::blitter_old::ColourSpace;
+
+/// D50-adapted colorant XYZ of each recognised space, red, green then blue,
+/// as stored in `rXYZ`, `gXYZ` and `bXYZ`.
+const COLORANTS: [(ColourSpace, [[f32; 3]; 3]); 3] = [
+    (
+        ColourSpace::Srgb,
+        [
+            [0.4361, 0.2225, 0.0139],
+            [0.3851, 0.7169, 0.0971],
+            [0.1431, 0.0606, 0.7141],
+        ],
+    ),
+    (
+        ColourSpace::DisplayP3,
+        [
+            [0.5151, 0.2412, -0.0011],
+            [0.2920, 0.6922, 0.0419],
+            [0.1571, 0.0666, 0.7841],
+        ],
+    ),
+    (
+        ColourSpace::AdobeRgb,
+        [
+            [0.6097, 0.3111, 0.0195],
+            [0.2053, 0.6257, 0.0609],
+            [0.1492, 0.0632, 0.7446],
+        ],
+    ),
+];
+
+/// Profiles round their colorants differently, but the recognised spaces
+/// are much further apart than this.
+const TOLERANCE: f32 = 0.02;
+
+/// Matches an RGB profile by its colorants, falling back to the description
+/// for profiles without them. `None` for anything else, which is best shown
+/// as sRGB.
+pub fn recognise(profile: &[u8]) -> Option<ColourSpace> {
+    if profile.get(16..20)? != b"RGB " {
+        return None;
+    }
+    let colorants = [b"rXYZ", b"gXYZ", b"bXYZ"].map(|signature| xyz(find_tag(profile, signature)?));
+    if let [Some(r), Some(g), Some(b)] = colorants {
+        let measured = [r, g, b];
+        return COLORANTS.iter().find_map(|&(space, expected)| {
+            let close = measured
+                .iter()
+                .flatten()
+                .zip(expected.iter().flatten())
+                .all(|(a, b)| (a - b).abs() <= TOLERANCE);
+            close.then_some(space)
+        });
+    }
+
+    let description = description(find_tag(profile, b"desc")?)?;
+    if description.contains("Display P3") {
+        Some(ColourSpace::DisplayP3)
+    } else if description.contains("Adobe RGB") {
+        Some(ColourSpace::AdobeRgb)
+    } else if description.contains("sRGB") {
+        Some(ColourSpace::Srgb)
+    } else {
+        None
+    }
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn find_tag<'a>(profile: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+    // The count comes from the file; only as many entries as fit are read.
+    let count = (be_u32(profile, 128)? as usize).min(profile.len().saturating_sub(132) / 12);
+    (0..count).find_map(|i| {
+        let entry = profile.get(132 + i * 12..144 + i * 12)?;
+        if &entry[..4] != signature {
+            return None;
+        }
+        let offset = be_u32(entry, 4)? as usize;
+        let size = be_u32(entry, 8)? as usize;
+        profile.get(offset..offset.checked_add(size)?)
+    })
+}
+
+/// An `XYZ ` tag holding one s15Fixed16 triple.
+fn xyz(tag: &[u8]) -> Option<[f32; 3]> {
+    if tag.get(..4)? != b"XYZ " {
+        return None;
+    }
+    let fixed = |i: usize| Some(be_u32(tag, 8 + i * 4)? as i32 as f32 / 65536.);
+    Some([fixed(0)?, fixed(1)?, fixed(2)?])
+}
+
+/// The ASCII `desc` of v2 profiles or the first `mluc` record of v4 ones.
+fn description(tag: &[u8]) -> Option<String> {
+    match tag.get(..4)? {
+        b"desc" => {
+            let len = be_u32(tag, 8)? as usize;
+            let ascii = tag.get(12..12 + len)?;
+            Some(
+                String::from_utf8_lossy(ascii)
+                    .trim_end_matches('\0')
+                    .to_owned(),
+            )
+        }
+        b"mluc" => {
+            let len = be_u32(tag, 20)? as usize;
+            let offset = be_u32(tag, 24)? as usize;
+            let utf16: Vec<u16> = tag
+                .get(offset..offset + len)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            Some(String::from_utf16_lossy(&utf16))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A profile holding just a header and `tags`.
+    fn profile(space: &[u8; 4], tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut header = vec![0; 128];
+        header[16..20].copy_from_slice(space);
+        let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut data = Vec::new();
+        let mut offset = 128 + 4 + tags.len() * 12;
+        for (signature, tag) in tags {
+            table.extend_from_slice(*signature);
+            table.extend((offset as u32).to_be_bytes());
+            table.extend((tag.len() as u32).to_be_bytes());
+            data.extend_from_slice(tag);
+            offset += tag.len();
+        }
+        [header, table, data].concat()
+    }
+
+    fn xyz_tag([x, y, z]: [f32; 3]) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for v in [x, y, z] {
+            tag.extend(((v * 65536.).round() as i32).to_be_bytes());
+        }
+        tag
+    }
+
+    fn colorant_profile([r, g, b]: [[f32; 3]; 3]) -> Vec<u8> {
+        profile(
+            b"RGB ",
+            &[
+                (b"rXYZ", xyz_tag(r)),
+                (b"gXYZ", xyz_tag(g)),
+                (b"bXYZ", xyz_tag(b)),
+            ],
+        )
+    }
+
+    #[test]
+    fn recognises_colorants() {
+        for (space, colorants) in COLORANTS {
+            // Rounded differently from the reference values.
+            let nudged = colorants.map(|xyz| xyz.map(|v| v + 0.004));
+            assert_eq!(recognise(&colorant_profile(nudged)), Some(space));
+        }
+        let rec2020 = [
+            [0.6734, 0.2790, -0.0019],
+            [0.1656, 0.6753, 0.0299],
+            [0.1251, 0.0457, 0.7972],
+        ];
+        assert_eq!(recognise(&colorant_profile(rec2020)), None);
+    }
+
+    #[test]
+    fn falls_back_to_descriptions() {
+        let mut desc = b"desc\0\0\0\0".to_vec();
+        desc.extend(11u32.to_be_bytes());
+        desc.extend(b"Display P3\0");
+        assert_eq!(
+            recognise(&profile(b"RGB ", &[(b"desc", desc)])),
+            Some(ColourSpace::DisplayP3)
+        );
+
+        let text: Vec<u8> = "Adobe RGB (1998)"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let mut mluc = b"mluc\0\0\0\0".to_vec();
+        for field in [1, 12, u32::from_be_bytes(*b"enUS")] {
+            mluc.extend(field.to_be_bytes());
+        }
+        mluc.extend((text.len() as u32).to_be_bytes());
+        mluc.extend(28u32.to_be_bytes());
+        mluc.extend(text);
+        assert_eq!(
+            recognise(&profile(b"RGB ", &[(b"desc", mluc)])),
+            Some(ColourSpace::AdobeRgb)
+        );
+    }
+
+    #[test]
+    fn rejects_other_and_malformed_profiles() {
+        let srgb = colorant_profile(COLORANTS[0].1);
+        let mut gray = srgb.clone();
+        gray[16..20].copy_from_slice(b"GRAY");
+        assert_eq!(recognise(&gray), None);
+
+        // A huge tag count must not be walked past the end of the data.
+        let mut truncated = srgb[..140].to_vec();
+        truncated[128..132].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(recognise(&truncated), None);
+        assert_eq!(recognise(&srgb[..100]), None);
+    }
+}
@@ -0,0 +1,18 @@
+pub mod bc;
+pub mod blit_options;
+pub mod blitter_new;
+pub mod blitter_old;
+pub mod color_adjust;
+pub mod compressed;
+pub mod dither;
+pub mod golden;
+pub mod hot_reload;
+pub mod icc;
+pub mod loader;
+pub mod lut;
+pub mod reference;
+pub mod rgbe;
+pub mod subresource;
+pub mod tiled;
+pub mod upload;
+pub mod yuv;
@@ -0,0 +1,364 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use image::ImageDecoder;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    blit_options::{BlitOptions, Orientation, Swizzle},
+    blitter_old::ColourSpace,
+    icc,
+    tiled::TiledTexture,
+};
+
+/// How HDR pixels end up on the GPU.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HdrStorage {
+    /// Raw shared-exponent texels in `Rgba8Unorm`, decoded by the blit shaders.
+    Rgbe,
+    #[default]
+    Half,
+    /// `Rgba32Float`, which is only filterable through
+    /// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` on adapters that allow it.
+    /// Falls back to `Half` on devices without that feature, as the blitters
+    /// filter every source.
+    Float,
+}
+
+pub struct LoadedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub space: ColourSpace,
+    /// How to show the channels as colour, e.g. grayscale from `R8Unorm`.
+    pub swizzle: Swizzle,
+    /// From EXIF; the data itself is stored unrotated.
+    pub orientation: Orientation,
+    pub data: Vec<u8>,
+}
+
+impl LoadedImage {
+    /// Options that display the image as intended; combine with
+    /// `..image.blit_options()`.
+    pub fn blit_options(&self) -> BlitOptions<'static> {
+        BlitOptions {
+            swizzle: self.swizzle,
+            orientation: self.orientation,
+            source_space: self.space,
+            ..Default::default()
+        }
+    }
+
+    /// Size once `orientation` is applied.
+    pub fn display_size(&self) -> (u32, u32) {
+        if self.orientation.swaps_dimensions() {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    fn texture_descriptor<'a>(
+        &self,
+        label: Option<&'a str>,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::TextureDescriptor<'a> {
+        wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage,
+            view_formats: &[],
+        }
+    }
+
+    pub fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
+        device.create_texture_with_data(queue, &self.texture_descriptor(label, usage), &self.data)
+    }
+
+    /// For images that may exceed `max_texture_dimension_2d`.
+    pub fn create_tiled_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        usage: wgpu::TextureUsages,
+    ) -> TiledTexture {
+        TiledTexture::new(
+            device,
+            queue,
+            &self.texture_descriptor(label, usage),
+            &self.data,
+        )
+    }
+
+    /// Empty tiles to fill later, e.g. by an `upload::UploadQueue`.
+    pub fn allocate_tiled_texture(
+        &self,
+        device: &wgpu::Device,
+        label: Option<&str>,
+        usage: wgpu::TextureUsages,
+    ) -> TiledTexture {
+        TiledTexture::allocate(device, &self.texture_descriptor(label, usage))
+    }
+}
+
+/// `features` decides whether 16-bit images can use `*16Unorm` formats and
+/// HDR ones `Rgba32Float`.
+pub fn load(
+    path: impl AsRef<Path>,
+    hdr_storage: HdrStorage,
+    features: wgpu::Features,
+) -> Result<LoadedImage> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("hdr") => load_hdr(path, hdr_storage, features),
+        Some("exr") => load_exr(path, hdr_storage, features),
+        _ => load_sdr(path, features),
+    }
+}
+
+/// Embedded ICC profile and EXIF orientation, for the formats that carry
+/// them.
+fn read_metadata(bytes: &[u8]) -> (Option<Vec<u8>>, Orientation) {
+    use image::{codecs, ImageFormat};
+
+    let profile = match image::guess_format(bytes) {
+        Ok(ImageFormat::Png) => codecs::png::PngDecoder::new(bytes)
+            .ok()
+            .and_then(|mut d| d.icc_profile()),
+        Ok(ImageFormat::Jpeg) => codecs::jpeg::JpegDecoder::new(bytes)
+            .ok()
+            .and_then(|mut d| d.icc_profile()),
+        Ok(ImageFormat::Tiff) => codecs::tiff::TiffDecoder::new(Cursor::new(bytes))
+            .ok()
+            .and_then(|mut d| d.icc_profile()),
+        Ok(ImageFormat::WebP) => codecs::webp::WebPDecoder::new(bytes)
+            .ok()
+            .and_then(|mut d| d.icc_profile()),
+        _ => None,
+    };
+    let orientation = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+                .value
+                .get_uint(0)
+        })
+        .and_then(Orientation::from_exif)
+        .unwrap_or_default();
+    (profile, orientation)
+}
+
+/// Keeps the source's channel count and bit depth. RGB is padded to RGBA,
+/// as there are no three-channel formats. Only 8-bit RGBA has an sRGB
+/// format; everything else is tagged `Srgb` for the blit shaders to decode.
+/// Display P3 and Adobe RGB profiles are tagged with their space instead, and
+/// the EXIF orientation is kept for the blit to apply.
+pub fn load_sdr(path: impl AsRef<Path>, features: wgpu::Features) -> Result<LoadedImage> {
+    use image::DynamicImage as I;
+    use wgpu::TextureFormat as F;
+
+    let path = path.as_ref();
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (profile, orientation) = read_metadata(&bytes);
+    let image = image::load_from_memory(&bytes)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let (width, height) = (image.width(), image.height());
+    let unorm16 = features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM);
+    let sixteen_bit = |data: Vec<u16>, unorm: F, float: F| {
+        if unorm16 {
+            (unorm, bytemuck::cast_slice(&data).to_vec())
+        } else {
+            let data = data
+                .into_iter()
+                .flat_map(|v| half::f16::from_f32(v as f32 / 65535.).to_le_bytes())
+                .collect();
+            (float, data)
+        }
+    };
+
+    let (format, space, swizzle, data) = match image {
+        I::ImageLuma8(image) => (
+            F::R8Unorm,
+            ColourSpace::Srgb,
+            Swizzle::GRAYSCALE,
+            image.into_raw(),
+        ),
+        I::ImageLumaA8(image) => (
+            F::Rg8Unorm,
+            ColourSpace::Srgb,
+            Swizzle::GRAYSCALE_ALPHA,
+            image.into_raw(),
+        ),
+        I::ImageLuma16(image) => {
+            let (format, data) = sixteen_bit(image.into_raw(), F::R16Unorm, F::R16Float);
+            (format, ColourSpace::Srgb, Swizzle::GRAYSCALE, data)
+        }
+        I::ImageLumaA16(image) => {
+            let (format, data) = sixteen_bit(image.into_raw(), F::Rg16Unorm, F::Rg16Float);
+            (format, ColourSpace::Srgb, Swizzle::GRAYSCALE_ALPHA, data)
+        }
+        image @ (I::ImageRgb16(_) | I::ImageRgba16(_)) => {
+            let rgba = image.into_rgba16().into_raw();
+            let (format, data) = sixteen_bit(rgba, F::Rgba16Unorm, F::Rgba16Float);
+            (format, ColourSpace::Srgb, Swizzle::IDENTITY, data)
+        }
+        image @ (I::ImageRgb32F(_) | I::ImageRgba32F(_)) => {
+            let rgba = image.into_rgba32f().into_raw();
+            let (format, space, data) = float_storage(rgba.into_iter(), HdrStorage::Half, features);
+            (format, space, Swizzle::IDENTITY, data)
+        }
+        image => (
+            F::Rgba8UnormSrgb,
+            ColourSpace::Linear,
+            Swizzle::IDENTITY,
+            image.into_rgba8().into_raw(),
+        ),
+    };
+
+    // Hardware sRGB decoding would leave the primaries unconverted, so wider
+    // spaces are decoded entirely in the blit shaders. There's no space for
+    // linear wide-gamut floats, so those stay as they are.
+    let encoded = format.is_srgb() || space == ColourSpace::Srgb;
+    let (format, space) = match profile.as_deref().and_then(icc::recognise) {
+        Some(wide @ (ColourSpace::DisplayP3 | ColourSpace::AdobeRgb)) if encoded => {
+            log::info!("{} has a {wide:?} ICC profile", path.display());
+            (format.remove_srgb_suffix(), wide)
+        }
+        _ => (format, space),
+    };
+    Ok(LoadedImage {
+        width,
+        height,
+        format,
+        space,
+        swizzle,
+        orientation,
+        data,
+    })
+}
+
+pub fn load_hdr(
+    path: impl AsRef<Path>,
+    storage: HdrStorage,
+    features: wgpu::Features,
+) -> Result<LoadedImage> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let decoder = image::codecs::hdr::HdrDecoder::new(BufReader::new(file))?;
+    let meta = decoder.metadata();
+    let (format, space, data) = match storage {
+        HdrStorage::Rgbe => {
+            let pixels = decoder.read_image_native()?;
+            let data = pixels
+                .iter()
+                .flat_map(|p| [p.c[0], p.c[1], p.c[2], p.e])
+                .collect();
+            (wgpu::TextureFormat::Rgba8Unorm, ColourSpace::Rgbe, data)
+        }
+        HdrStorage::Half | HdrStorage::Float => {
+            let pixels = decoder.read_image_hdr()?;
+            let rgba = pixels.iter().flat_map(|p| [p[0], p[1], p[2], 1.]);
+            float_storage(rgba, storage, features)
+        }
+    };
+    Ok(LoadedImage {
+        width: meta.width,
+        height: meta.height,
+        format,
+        space,
+        swizzle: Swizzle::IDENTITY,
+        orientation: Orientation::Normal,
+        data,
+    })
+}
+
+pub fn load_exr(
+    path: impl AsRef<Path>,
+    storage: HdrStorage,
+    features: wgpu::Features,
+) -> Result<LoadedImage> {
+    let path = path.as_ref();
+    if storage == HdrStorage::Rgbe {
+        bail!("OpenEXR images can't be stored as RGBE");
+    }
+    let image = image::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .into_rgba32f();
+    let (width, height) = image.dimensions();
+    let (format, space, data) = float_storage(image.into_raw().into_iter(), storage, features);
+    Ok(LoadedImage {
+        width,
+        height,
+        format,
+        space,
+        swizzle: Swizzle::IDENTITY,
+        orientation: Orientation::Normal,
+        data,
+    })
+}
+
+fn float_storage(
+    rgba: impl Iterator<Item = f32>,
+    storage: HdrStorage,
+    features: wgpu::Features,
+) -> (wgpu::TextureFormat, ColourSpace, Vec<u8>) {
+    let filterable = features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    if storage == HdrStorage::Float && !filterable {
+        log::warn!("Rgba32Float isn't filterable on this device, storing HDR as half floats");
+    }
+    match storage {
+        HdrStorage::Float if filterable => (
+            wgpu::TextureFormat::Rgba32Float,
+            ColourSpace::Linear,
+            rgba.flat_map(f32::to_le_bytes).collect(),
+        ),
+        _ => (
+            wgpu::TextureFormat::Rgba16Float,
+            ColourSpace::Linear,
+            rgba.flat_map(|v| half::f16::from_f32(v).to_le_bytes())
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_storage_needs_filterable_floats() {
+        let rgba = [1., 2., 3., 1.];
+        let storage = |features| float_storage(rgba.into_iter(), HdrStorage::Float, features);
+        let (format, _, data) = storage(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        assert_eq!(format, wgpu::TextureFormat::Rgba32Float);
+        assert_eq!(data.len(), 16);
+        let (format, _, data) = storage(wgpu::Features::empty());
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Float);
+        assert_eq!(data.len(), 8);
+    }
+}
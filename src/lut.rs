@@ -0,0 +1,234 @@
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use wgpu::util::DeviceExt;
+
+/// Parsed Adobe `.cube` 3D LUT. Entries are stored red-fastest, as in the file.
+#[derive(Clone, Debug)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.; 3];
+        let mut domain_max = [1.; 3];
+        let mut data = Vec::new();
+
+        let parse_triplet = |words: &[&str], line: usize| -> Result<[f32; 3]> {
+            ensure!(words.len() == 3, "line {line}: expected 3 values");
+            let mut triplet = [0.; 3];
+            for (value, word) in triplet.iter_mut().zip(words) {
+                *value = word
+                    .parse()
+                    .with_context(|| format!("line {line}: invalid number {word:?}"))?;
+            }
+            Ok(triplet)
+        };
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "TITLE" => title = Some(line["TITLE".len()..].trim().trim_matches('"').to_owned()),
+                "LUT_3D_SIZE" => {
+                    let n: u32 = words
+                        .get(1)
+                        .context("LUT_3D_SIZE without a value")?
+                        .parse()
+                        .with_context(|| format!("line {line_number}: invalid LUT_3D_SIZE"))?;
+                    ensure!((2..=256).contains(&n), "LUT_3D_SIZE {n} out of range");
+                    size = Some(n);
+                }
+                "DOMAIN_MIN" => domain_min = parse_triplet(&words[1..], line_number)?,
+                "DOMAIN_MAX" => domain_max = parse_triplet(&words[1..], line_number)?,
+                "LUT_1D_SIZE" => bail!("1D LUTs are not supported"),
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = [words.get(1), words.get(2)]
+                        .map(|word| word.and_then(|word| word.parse::<f32>().ok()));
+                    let (Some(min), Some(max)) = (min, max) else {
+                        bail!("line {line_number}: invalid input range");
+                    };
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                word if word.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    log::warn!("Ignoring unknown .cube keyword {word}");
+                }
+                _ => data.push(parse_triplet(&words, line_number)?),
+            }
+        }
+
+        let size = size.context("Missing LUT_3D_SIZE")?;
+        ensure!(
+            data.len() == (size * size * size) as usize,
+            "Expected {} LUT entries, found {}",
+            size * size * size,
+            data.len()
+        );
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+/// Mirrors `LutDomain` in `blit_new.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LutDomain {
+    min: [f32; 4],
+    max: [f32; 4],
+}
+
+/// A `.cube` LUT uploaded as an `Rgba16Float` 3D texture.
+#[derive(Debug)]
+pub struct Lut3d {
+    pub size: u32,
+    pub texture: wgpu::Texture,
+    pub(crate) view: wgpu::TextureView,
+    pub(crate) domain: wgpu::Buffer,
+}
+
+impl Lut3d {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, lut: &CubeLut) -> Self {
+        let data: Vec<u8> = lut
+            .data
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.])
+            .flat_map(|v| half::f16::from_f32(v).to_le_bytes())
+            .collect();
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: lut.title.as_deref().or(Some("3D LUT")),
+                size: wgpu::Extent3d {
+                    width: lut.size,
+                    height: lut.size,
+                    depth_or_array_layers: lut.size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            &data,
+        );
+        let [min_r, min_g, min_b] = lut.domain_min;
+        let [max_r, max_g, max_b] = lut.domain_max;
+        let domain = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("3D LUT Domain"),
+            contents: bytemuck::bytes_of(&LutDomain {
+                min: [min_r, min_g, min_b, 0.],
+                max: [max_r, max_g, max_b, 0.],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        Self {
+            size: lut.size,
+            view: texture.create_view(&Default::default()),
+            texture,
+            domain,
+        }
+    }
+
+    pub fn load_cube(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        Ok(Self::new(device, queue, &CubeLut::load(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An identity 2x2x2 cube, red fastest.
+    const IDENTITY: &str = "\
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    #[test]
+    fn parses_minimal_cube() {
+        let lut = CubeLut::parse(&format!(
+            "# Comment\nTITLE \"Identity\"\n\nLUT_3D_SIZE 2\n{IDENTITY}"
+        ))
+        .unwrap();
+        assert_eq!(lut.title.as_deref(), Some("Identity"));
+        assert_eq!(lut.size, 2);
+        assert_eq!((lut.domain_min, lut.domain_max), ([0.; 3], [1.; 3]));
+        assert_eq!(lut.data.len(), 8);
+        assert_eq!(lut.data[1], [1., 0., 0.]);
+        assert_eq!(lut.data[6], [0., 1., 1.]);
+    }
+
+    #[test]
+    fn parses_domain() {
+        let lut = CubeLut::parse(&format!(
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 0 -0.5 0\nDOMAIN_MAX 1 2 4.5\n{IDENTITY}"
+        ))
+        .unwrap();
+        assert_eq!(lut.domain_min, [0., -0.5, 0.]);
+        assert_eq!(lut.domain_max, [1., 2., 4.5]);
+    }
+
+    fn error(source: &str) -> String {
+        format!("{:#}", CubeLut::parse(source).unwrap_err())
+    }
+
+    #[test]
+    fn rejects_wrong_entry_count() {
+        let short = IDENTITY.lines().skip(1).collect::<Vec<_>>().join("\n");
+        assert_eq!(
+            error(&format!("LUT_3D_SIZE 2\n{short}")),
+            "Expected 8 LUT entries, found 7"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_size() {
+        assert_eq!(error(IDENTITY), "Missing LUT_3D_SIZE");
+    }
+
+    #[test]
+    fn rejects_non_numeric_rows() {
+        let message = error(&format!("LUT_3D_SIZE 2\n0 0 zero\n{IDENTITY}"));
+        assert!(
+            message.starts_with("line 2: invalid number \"zero\""),
+            "{message}"
+        );
+        let message = error("LUT_3D_SIZE 2\n0 0\n");
+        assert_eq!(message, "line 2: expected 3 values");
+    }
+}
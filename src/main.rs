@@ -1,3 +1,5 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::Result;
 use pollster::FutureExt;
 use wgpu::{util::DeviceExt, TextureFormat};
@@ -7,9 +9,14 @@ use winit::{
     event_loop::ControlFlow,
 };
 
-mod blitter_new;
-mod blitter_old;
-use blitter_old::Blitter;
+use blittin_test::{
+    blit_options::{BlitOptions, Tonemap},
+    blitter_new, blitter_old,
+    blitter_old::Blitter,
+    hot_reload::{self, ShaderWatcher},
+    loader::{self, HdrStorage},
+    upload::UploadQueue,
+};
 
 fn main() -> Result<()> {
     env_logger::builder()
@@ -51,7 +58,11 @@ fn main() -> Result<()> {
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: Some("GPU Device"),
-                features: wgpu::Features::empty(),
+                // BC textures are decoded on the CPU and 16-bit images
+                // uploaded as half floats when these are missing.
+                features: adapter.features()
+                    & (wgpu::Features::TEXTURE_COMPRESSION_BC
+                        | wgpu::Features::TEXTURE_FORMAT_16BIT_NORM),
                 limits: wgpu::Limits::default(),
             },
             None,
@@ -62,13 +73,18 @@ fn main() -> Result<()> {
     surface_config.format = wgpu::TextureFormat::Bgra8UnormSrgb;
     surface.configure(&device, &surface_config);
 
-    let cat_pic = image::open("catfish.png")?.into_rgba8();
-    let cat_format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let cat_pic = loader::load("catfish.png", HdrStorage::default(), device.features())?;
+    let cat_format = cat_pic.format;
+    // Grayscale and 16-bit formats have no sRGB variant, so both are the same.
+    let (cat_srgb_view, cat_norm_view) = (
+        cat_format.add_srgb_suffix(),
+        cat_format.remove_srgb_suffix(),
+    );
     let mut cat_texture_desc = wgpu::TextureDescriptor {
         label: Some("Catfish"),
         size: wgpu::Extent3d {
-            width: cat_pic.width(),
-            height: cat_pic.height(),
+            width: cat_pic.width,
+            height: cat_pic.height,
             depth_or_array_layers: 1,
         },
         mip_level_count: 1,
@@ -76,17 +92,24 @@ fn main() -> Result<()> {
         dimension: wgpu::TextureDimension::D2,
         format: cat_format,
         usage: wgpu::TextureUsages::TEXTURE_BINDING,
-        view_formats: &[
-            cat_format.add_srgb_suffix(),
-            cat_format.remove_srgb_suffix(),
-        ],
+        view_formats: &[cat_srgb_view, cat_norm_view],
     };
     let cat_texture_srgb =
-        device.create_texture_with_data(&queue, &cat_texture_desc, cat_pic.as_raw());
+        device.create_texture_with_data(&queue, &cat_texture_desc, &cat_pic.data);
 
-    cat_texture_desc.format = cat_format.remove_srgb_suffix();
+    cat_texture_desc.format = cat_norm_view;
     let cat_texture_norm =
-        device.create_texture_with_data(&queue, &cat_texture_desc, cat_pic.as_raw());
+        device.create_texture_with_data(&queue, &cat_texture_desc, &cat_pic.data);
+
+    // Decoded and streamed in the background; a placeholder shows until then.
+    let mut uploads = UploadQueue::new(&device, &queue, 4 << 20);
+    let mut hdr_upload = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .map(|path| uploads.load(path, HdrStorage::Rgbe, wgpu::TextureUsages::TEXTURE_BINDING));
+    // Failed loads were logged and keep showing the placeholder.
+    let show_hdr = hdr_upload.is_some();
+    let mut hdr_image = None;
 
     let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
         address_mode_u: wgpu::AddressMode::Repeat,
@@ -148,33 +171,34 @@ fn main() -> Result<()> {
     let tex_norm_view_srgb = create_bg(&cat_texture_norm, true);
     let tex_norm_view_norm = create_bg(&cat_texture_norm, false);
 
-    let shader = device.create_shader_module(wgpu::include_wgsl!("trig.wgsl"));
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Pipeline Desc"),
         bind_group_layouts: &[&bind_group_layout],
         push_constant_ranges: &[],
     });
+    let mut pipeline = create_trig_pipeline(
+        &device,
+        &pipeline_layout,
+        surface_config.format,
+        include_str!("trig.wgsl"),
+    );
+    let mut blit_old_source = include_str!("blit_old.wgsl").to_owned();
 
-    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main_full",
-            buffers: &[],
-        },
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(surface_config.format.into())],
-        }),
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
+    // With --hot-reload, shaders are read from the source tree at startup and
+    // again whenever they're saved.
+    let shader_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let watcher = std::env::args()
+        .any(|arg| arg == "--hot-reload")
+        .then(|| ShaderWatcher::new(&shader_dir))
+        .transpose()?;
+    let mut reloads: Vec<PathBuf> = match watcher {
+        Some(_) => ["blit_new.wgsl", "blit_old.wgsl", "trig.wgsl"]
+            .map(|name| shader_dir.join(name))
+            .into(),
+        None => Vec::new(),
+    };
 
-    let new_blitter = blitter_new::Blitter::new(&device);
+    let mut new_blitter = blitter_new::Blitter::new(&device);
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
@@ -182,16 +206,63 @@ fn main() -> Result<()> {
         match event {
             Event::RedrawEventsCleared => window.request_redraw(),
             Event::RedrawRequested(_) => {
-                let Ok(frame) = surface.get_current_texture() else { return; };
+                let Ok(frame) = surface.get_current_texture() else {
+                    return;
+                };
                 let frame_view = frame.texture.create_view(&Default::default());
 
+                if let Some(watcher) = &watcher {
+                    reloads.extend(watcher.changed());
+                }
+                for path in reloads.drain(..) {
+                    let name = path.file_name().and_then(|name| name.to_str());
+                    let source = hot_reload::load_wgsl(&path);
+                    let reloaded = match name {
+                        Some("blit_new.wgsl") => {
+                            source.and_then(|source| new_blitter.reload_shader(&device, &source))
+                        }
+                        Some("blit_old.wgsl") => source.and_then(|source| {
+                            hot_reload::try_build(&device, || {
+                                Blitter::with_shader(
+                                    &device,
+                                    &cat_texture_srgb.create_view(&Default::default()),
+                                    blitter_old::ColourSpace::Linear,
+                                    surface_config.format,
+                                    &source,
+                                )
+                            })??;
+                            blit_old_source = source;
+                            Ok(())
+                        }),
+                        Some("trig.wgsl") => source.and_then(|source| {
+                            pipeline = hot_reload::try_build(&device, || {
+                                create_trig_pipeline(
+                                    &device,
+                                    &pipeline_layout,
+                                    surface_config.format,
+                                    &source,
+                                )
+                            })?;
+                            Ok(())
+                        }),
+                        _ => continue,
+                    };
+                    match reloaded {
+                        Ok(()) => log::info!("Reloaded {}", path.display()),
+                        Err(error) => {
+                            log::error!("Keeping the previous {}: {error:#}", path.display())
+                        }
+                    }
+                }
+
                 let width = surface_config.width as f32;
                 let height = surface_config.height as f32;
                 let woff = width / 4.;
-                let hoff = height / 3.;
+                let rows = if hdr_upload.is_some() { 4. } else { 3. };
+                let hoff = height / rows;
 
                 let create_old_blitter = |tex: &wgpu::Texture, format| {
-                    Blitter::new(
+                    Blitter::with_shader(
                         &device,
                         &tex.create_view(&wgpu::TextureViewDescriptor {
                             format: Some(format),
@@ -199,7 +270,9 @@ fn main() -> Result<()> {
                         }),
                         blitter_old::ColourSpace::Linear,
                         surface_config.format,
+                        &blit_old_source,
                     )
+                    .expect("linear sources blit to the sRGB surface")
                 };
                 let blit_new =
                     |encoder: &mut wgpu::CommandEncoder, tex: &wgpu::Texture, format, dims| {
@@ -249,17 +322,17 @@ fn main() -> Result<()> {
                 rpass.draw(0..3, 0..1);
                 drop(rpass);
 
-                let blitter = create_old_blitter(&cat_texture_srgb, TextureFormat::Rgba8UnormSrgb);
+                let blitter = create_old_blitter(&cat_texture_srgb, cat_srgb_view);
                 blitter.blit_with_viewport(&mut encoder, &frame_view, (0., hoff, woff, hoff));
-                let blitter = create_old_blitter(&cat_texture_srgb, TextureFormat::Rgba8Unorm);
+                let blitter = create_old_blitter(&cat_texture_srgb, cat_norm_view);
                 blitter.blit_with_viewport(&mut encoder, &frame_view, (woff, hoff, woff, hoff));
-                let blitter = create_old_blitter(&cat_texture_srgb, TextureFormat::Rgba8UnormSrgb);
+                let blitter = create_old_blitter(&cat_texture_srgb, cat_srgb_view);
                 blitter.blit_with_viewport(
                     &mut encoder,
                     &frame_view,
                     (2. * woff, hoff, woff, hoff),
                 );
-                let blitter = create_old_blitter(&cat_texture_srgb, TextureFormat::Rgba8Unorm);
+                let blitter = create_old_blitter(&cat_texture_srgb, cat_norm_view);
                 blitter.blit_with_viewport(
                     &mut encoder,
                     &frame_view,
@@ -269,29 +342,70 @@ fn main() -> Result<()> {
                 blit_new(
                     &mut encoder,
                     &cat_texture_srgb,
-                    TextureFormat::Rgba8UnormSrgb,
+                    cat_srgb_view,
                     (0., 2. * hoff, woff, hoff),
                 );
                 blit_new(
                     &mut encoder,
                     &cat_texture_srgb,
-                    TextureFormat::Rgba8Unorm,
+                    cat_norm_view,
                     (woff, 2. * hoff, woff, hoff),
                 );
                 blit_new(
                     &mut encoder,
                     &cat_texture_norm,
-                    TextureFormat::Rgba8UnormSrgb,
+                    cat_srgb_view,
                     (2. * woff, 2. * hoff, woff, hoff),
                 );
                 blit_new(
                     &mut encoder,
                     &cat_texture_norm,
-                    TextureFormat::Rgba8Unorm,
+                    cat_norm_view,
                     (3. * woff, 2. * hoff, woff, hoff),
                 );
 
+                if let Some(taken) = hdr_upload.and_then(|handle| uploads.take(handle)) {
+                    hdr_upload = None;
+                    hdr_image = taken.ok();
+                }
+                if show_hdr {
+                    let tonemaps = [
+                        Tonemap::None,
+                        Tonemap::Reinhard,
+                        Tonemap::AcesFitted,
+                        Tonemap::AgX,
+                    ];
+                    for (i, tonemap) in tonemaps.into_iter().enumerate() {
+                        let target =
+                            blitter_new::BlitTarget::new(&frame_view, surface_config.format);
+                        let viewport = (i as f32 * woff, 3. * hoff, woff, hoff);
+                        match &hdr_image {
+                            Some((image, texture)) => new_blitter.blit_tiled(
+                                &mut encoder,
+                                &device,
+                                texture,
+                                target,
+                                &BlitOptions {
+                                    tonemap,
+                                    ..image.blit_options()
+                                },
+                                viewport,
+                            ),
+                            None => new_blitter.blit_to_target(
+                                &mut encoder,
+                                &device,
+                                uploads.placeholder(),
+                                target,
+                                viewport,
+                            ),
+                        }
+                    }
+                }
+
+                uploads.process(&device, &mut encoder);
+                uploads.finish();
                 queue.submit(Some(encoder.finish()));
+                uploads.recall();
                 frame.present();
             }
             Event::WindowEvent {
@@ -302,12 +416,10 @@ fn main() -> Result<()> {
                         ..
                     },
                 ..
-            } => {
-                if width != 0 && height != 0 {
-                    surface_config.width = width;
-                    surface_config.height = height;
-                    surface.configure(&device, &surface_config);
-                }
+            } if width != 0 && height != 0 => {
+                surface_config.width = width;
+                surface_config.height = height;
+                surface.configure(&device, &surface_config);
             }
             Event::WindowEvent {
                 event:
@@ -326,3 +438,33 @@ fn main() -> Result<()> {
         }
     })
 }
+
+fn create_trig_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: TextureFormat,
+    source: &str,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("trig.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main_full",
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
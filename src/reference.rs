@@ -0,0 +1,249 @@
+//! CPU mirror of the blit shaders, for computing the images GPU output is
+//! compared against. Sampling follows the WebGPU rules the shaders rely on:
+//! texel centres at half-integer coordinates, bilinear weights from the
+//! fractional part and the sampler's address mode for out-of-range texels.
+
+/// Texels as the shaders see them, after the format's own decoding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major RGBA.
+    pub texels: Vec<[f32; 4]>,
+}
+
+impl Image {
+    /// Transparent black.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::from_fn(width, height, |_, _| [0.; 4])
+    }
+
+    pub fn from_fn(width: u32, height: u32, mut f: impl FnMut(u32, u32) -> [f32; 4]) -> Self {
+        let texels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Self {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    /// `Rgba8Unorm` data, decoded as an `Rgba8UnormSrgb` texture would be
+    /// when `srgb` is set.
+    pub fn from_rgba8(width: u32, height: u32, data: &[u8], srgb: bool) -> Self {
+        assert_eq!(data.len(), width as usize * height as usize * 4);
+        let mut texels = data.chunks_exact(4).map(|texel| {
+            let [r, g, b, a] = [0, 1, 2, 3].map(|i| texel[i] as f32 / 255.);
+            match srgb {
+                true => [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a],
+                false => [r, g, b, a],
+            }
+        });
+        Self::from_fn(width, height, |_, _| texels.next().unwrap())
+    }
+
+    /// Quantises like an `Rgba8Unorm` target, or `Rgba8UnormSrgb` when `srgb`
+    /// is set.
+    pub fn to_rgba8(&self, srgb: bool) -> Vec<u8> {
+        self.texels
+            .iter()
+            .flat_map(|&[r, g, b, a]| {
+                let rgb = [r, g, b].map(|v| if srgb { linear_to_srgb(v) } else { v });
+                [rgb[0], rgb[1], rgb[2], a].map(|v| (v.clamp(0., 1.) * 255.).round() as u8)
+            })
+            .collect()
+    }
+
+    pub fn texel(&self, x: u32, y: u32) -> [f32; 4] {
+        self.texels[(y * self.width + x) as usize]
+    }
+
+    pub fn set_texel(&mut self, x: u32, y: u32, texel: [f32; 4]) {
+        self.texels[(y * self.width + x) as usize] = texel;
+    }
+}
+
+/// Matches `srgb_to_linear` in the blit shaders, clamp included.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    let v = v.clamp(0., 1.);
+    if v <= 0.04045 {
+        v * (1. / 12.92)
+    } else {
+        ((v + 0.055) * (1. / 1.055)).powf(2.4)
+    }
+}
+
+/// Matches `linear_to_srgb` in the blit shaders, clamp included.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.clamp(0., 1.);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Decodes a sampled, so already normalised and possibly filtered, RGBE
+/// texel like `fs_main_rgbe_to_linear`.
+pub fn rgbe_to_linear([r, g, b, e]: [f32; 4]) -> [f32; 4] {
+    let scale = (e * 255. - 128.).exp2();
+    [r * scale, g * scale, b * scale, 1.]
+}
+
+/// The fragment shaders of `blit_old.wgsl`, applied after sampling.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Conversion {
+    #[default]
+    None,
+    LinearToSrgb,
+    SrgbToLinear,
+    RgbeToLinear,
+}
+
+impl Conversion {
+    pub fn apply(self, texel: [f32; 4]) -> [f32; 4] {
+        let [r, g, b, a] = texel;
+        match self {
+            Conversion::None => texel,
+            Conversion::LinearToSrgb => {
+                [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a]
+            }
+            Conversion::SrgbToLinear => {
+                [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+            }
+            Conversion::RgbeToLinear => rgbe_to_linear(texel),
+        }
+    }
+}
+
+/// Texture coordinates the fullscreen triangle interpolates to at the centre
+/// of target pixel (`x`, `y`), or `None` if that centre is outside the
+/// viewport and the pixel isn't drawn.
+pub fn viewport_uv(x: u32, y: u32, (vx, vy, vw, vh): (f32, f32, f32, f32)) -> Option<[f32; 2]> {
+    let u = (x as f32 + 0.5 - vx) / vw;
+    let v = (y as f32 + 0.5 - vy) / vh;
+    ((0. ..1.).contains(&u) && (0. ..1.).contains(&v)).then_some([u, v])
+}
+
+/// Index of the texel `i` resolves to, or `None` for `ClampToBorder`'s
+/// border, which the blit samplers never use and is taken as transparent
+/// black.
+fn address(i: i64, size: u32, mode: wgpu::AddressMode) -> Option<u32> {
+    let size = size as i64;
+    let i = match mode {
+        wgpu::AddressMode::ClampToEdge => i.clamp(0, size - 1),
+        wgpu::AddressMode::Repeat => i.rem_euclid(size),
+        wgpu::AddressMode::MirrorRepeat => {
+            let i = i.rem_euclid(2 * size);
+            if i < size {
+                i
+            } else {
+                2 * size - 1 - i
+            }
+        }
+        wgpu::AddressMode::ClampToBorder => {
+            if !(0..size).contains(&i) {
+                return None;
+            }
+            i
+        }
+    };
+    Some(i as u32)
+}
+
+/// `textureSample` with a linear, single-level sampler.
+pub fn sample_bilinear(image: &Image, [u, v]: [f32; 2], mode: wgpu::AddressMode) -> [f32; 4] {
+    let x = u * image.width as f32 - 0.5;
+    let y = v * image.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |dx: i64, dy: i64| match (
+        address(x0 as i64 + dx, image.width, mode),
+        address(y0 as i64 + dy, image.height, mode),
+    ) {
+        (Some(x), Some(y)) => image.texel(x, y),
+        _ => [0.; 4],
+    };
+    let (t00, t10, t01, t11) = (texel(0, 0), texel(1, 0), texel(0, 1), texel(1, 1));
+    std::array::from_fn(|c| {
+        let top = t00[c] + (t10[c] - t00[c]) * fx;
+        let bottom = t01[c] + (t11[c] - t01[c]) * fx;
+        top + (bottom - top) * fy
+    })
+}
+
+/// Draws `src` over `viewport` of `target` like a blit pass: only pixels
+/// whose centres are inside the viewport are written, with `conversion`
+/// applied to each bilinear sample.
+pub fn blit(
+    src: &Image,
+    target: &mut Image,
+    viewport: (f32, f32, f32, f32),
+    mode: wgpu::AddressMode,
+    conversion: Conversion,
+) {
+    for y in 0..target.height {
+        for x in 0..target.width {
+            if let Some(uv) = viewport_uv(x, y, viewport) {
+                target.set_texel(x, y, conversion.apply(sample_bilinear(src, uv, mode)));
+            }
+        }
+    }
+}
+
+/// How far an image is from the expected one, per channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub max_abs: f32,
+    pub mean_abs: f32,
+    /// Pixel with the largest difference.
+    pub worst: (u32, u32),
+}
+
+impl Difference {
+    pub fn within(&self, tolerance: f32) -> bool {
+        self.max_abs <= tolerance
+    }
+}
+
+pub fn difference(expected: &Image, actual: &Image) -> Difference {
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height),
+        "Images differ in size"
+    );
+    let mut result = Difference {
+        max_abs: 0.,
+        mean_abs: 0.,
+        worst: (0, 0),
+    };
+    let mut total = 0.;
+    for (i, (e, a)) in expected.texels.iter().zip(&actual.texels).enumerate() {
+        let pixel_max = (0..4).map(|c| (e[c] - a[c]).abs()).fold(0., f32::max);
+        total += (0..4).map(|c| (e[c] - a[c]).abs() as f64).sum::<f64>();
+        if pixel_max > result.max_abs {
+            result.max_abs = pixel_max;
+            result.worst = (i as u32 % expected.width, i as u32 / expected.width);
+        }
+    }
+    result.mean_abs = (total / (expected.texels.len() * 4).max(1) as f64) as f32;
+    result
+}
+
+/// Panics, naming the worst pixel, if any channel differs by more than
+/// `tolerance`. One 8-bit step is `1. / 255.`.
+#[track_caller]
+pub fn assert_close(expected: &Image, actual: &Image, tolerance: f32) {
+    let diff = difference(expected, actual);
+    let (x, y) = diff.worst;
+    assert!(
+        diff.within(tolerance),
+        "Max error {} exceeds {tolerance} at ({x}, {y}): expected {:?}, got {:?} (mean error {})",
+        diff.max_abs,
+        expected.texel(x, y),
+        actual.texel(x, y),
+        diff.mean_abs
+    );
+}
@@ -0,0 +1,124 @@
+//! CPU versions of the shared-exponent encodings in `blit_new.wgsl`, for
+//! preparing data and checking GPU round trips.
+
+/// Largest value RGBE can hold, `(255 / 255) * 2^(255 - 128)`.
+pub const RGBE_MAX: f32 = 1.7014118e38;
+
+/// Largest value RGB9E5 can hold.
+pub const RGB9E5_MAX: f32 = 65408.;
+
+/// Matches `linear_to_rgbe`: the exponent is the smallest that keeps every
+/// channel at or below 1, as the blit shaders decode `rgb / 255 * 2^(e - 128)`.
+pub fn encode_rgbe(rgb: [f32; 3]) -> [u8; 4] {
+    let c = rgb.map(|v| v.clamp(0., RGBE_MAX));
+    let peak = c[0].max(c[1]).max(c[2]);
+    if peak < 1e-32 {
+        return [0; 4];
+    }
+    let e = peak.log2().ceil().clamp(-128., 127.);
+    let [r, g, b] = c.map(|v| (v * (-e).exp2() * 255.).round().min(255.) as u8);
+    [r, g, b, (e + 128.) as u8]
+}
+
+pub fn decode_rgbe([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    let scale = (e as f32 - 128.).exp2() / 255.;
+    [r, g, b].map(|v| v as f32 * scale)
+}
+
+/// Matches `linear_to_rgb9e5`, following EXT_texture_shared_exponent.
+pub fn encode_rgb9e5(rgb: [f32; 3]) -> u32 {
+    let c = rgb.map(|v| v.clamp(0., RGB9E5_MAX));
+    let peak = c[0].max(c[1]).max(c[2]);
+    let mut e = peak.log2().floor().max(-16.) + 16.;
+    if (peak * (24. - e).exp2() + 0.5).floor() >= 512. {
+        e += 1.;
+    }
+    let [r, g, b] = c.map(|v| (v * (24. - e).exp2() + 0.5).floor() as u32);
+    r | g << 9 | b << 18 | (e as u32) << 27
+}
+
+pub fn decode_rgb9e5(packed: u32) -> [f32; 3] {
+    let scale = ((packed >> 27) as f32 - 24.).exp2();
+    [0, 9, 18].map(|shift| ((packed >> shift) & 0x1ff) as f32 * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Linear values spanning the useful range of both encodings.
+    const SAMPLES: [[f32; 3]; 6] = [
+        [1., 0.5, 0.25],
+        [0.18, 0.18, 0.18],
+        [1000., 3., 0.],
+        [1e-3, 2e-3, 4e-3],
+        [6e4, 1., 1e-2],
+        [0.7, 0., 0.],
+    ];
+
+    fn assert_within(decoded: [f32; 3], rgb: [f32; 3], tolerance: f32) {
+        for c in 0..3 {
+            assert!(
+                (decoded[c] - rgb[c]).abs() <= tolerance,
+                "{rgb:?} decoded as {decoded:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rgbe_round_trips_within_one_mantissa_step() {
+        for rgb in SAMPLES {
+            let peak = rgb.iter().copied().fold(0., f32::max);
+            // Half a step of 2^e / 255, where 2^e < 2 * peak.
+            assert_within(decode_rgbe(encode_rgbe(rgb)), rgb, peak / 255.);
+        }
+    }
+
+    #[test]
+    fn rgbe_zero_denormals_and_overflow() {
+        assert_eq!(encode_rgbe([0.; 3]), [0; 4]);
+        assert_eq!(decode_rgbe([0; 4]), [0.; 3]);
+        // f32 denormals are far below the smallest exponent.
+        assert_eq!(encode_rgbe([1e-40, 0., 1e-39]), [0; 4]);
+        let clamped = decode_rgbe(encode_rgbe([f32::INFINITY, f32::MAX, 1.]));
+        assert_eq!(clamped[..2], [RGBE_MAX; 2]);
+    }
+
+    #[test]
+    fn rgb9e5_round_trips_within_half_a_step() {
+        for rgb in SAMPLES {
+            let peak = rgb.iter().copied().fold(0., f32::max);
+            // The peak keeps at least 8 of its 9 mantissa bits.
+            let tolerance = (peak / 512.).max(2f32.powi(-25));
+            assert_within(decode_rgb9e5(encode_rgb9e5(rgb)), rgb, tolerance);
+        }
+    }
+
+    #[test]
+    fn rgb9e5_zero_denormals_and_overflow() {
+        assert_eq!(encode_rgb9e5([0.; 3]), 0);
+        assert_eq!(decode_rgb9e5(0), [0.; 3]);
+        // Below the smallest exponent values become multiples of 2^-24.
+        let smallest = 2f32.powi(-24);
+        assert_eq!(
+            decode_rgb9e5(encode_rgb9e5([smallest, 3. * smallest, 1e-10])),
+            [smallest, 3. * smallest, 0.]
+        );
+        for above in [RGB9E5_MAX + 1., 1e6, f32::INFINITY] {
+            assert_eq!(decode_rgb9e5(encode_rgb9e5([above, 1., 0.]))[0], RGB9E5_MAX);
+        }
+        assert_eq!(encode_rgb9e5([RGB9E5_MAX; 3]), u32::MAX);
+    }
+
+    #[test]
+    fn rgb9e5_encode_inverts_decode() {
+        // Canonical encodings: the largest mantissa uses all nine bits.
+        for packed in [
+            0x1ff | 5 << 27,
+            0x100 | 0x80 << 9 | 20 << 27,
+            0x17f << 18 | 31 << 27,
+        ] {
+            assert_eq!(encode_rgb9e5(decode_rgb9e5(packed)), packed, "{packed:#x}");
+        }
+    }
+}
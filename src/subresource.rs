@@ -0,0 +1,192 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+}
+
+/// A single mip level of a single layer. `layer` is the array layer for 2D
+/// arrays and cube maps (`cube * 6 + face`) and the depth slice for 3D textures.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Subresource {
+    pub mip_level: u32,
+    pub layer: u32,
+}
+
+impl Subresource {
+    pub fn new(mip_level: u32, layer: u32) -> Self {
+        Self { mip_level, layer }
+    }
+
+    pub fn cube_face(mip_level: u32, cube: u32, face: CubeFace) -> Self {
+        Self {
+            mip_level,
+            layer: cube * 6 + face as u32,
+        }
+    }
+
+    pub fn extent(&self, texture: &wgpu::Texture) -> wgpu::Extent3d {
+        let size = texture
+            .size()
+            .mip_level_size(self.mip_level, texture.dimension());
+        wgpu::Extent3d {
+            depth_or_array_layers: 1,
+            ..size
+        }
+    }
+
+    fn validate(&self, texture: &wgpu::Texture) {
+        assert!(
+            self.mip_level < texture.mip_level_count(),
+            "Subresource: mip level {} out of range for {} levels",
+            self.mip_level,
+            texture.mip_level_count()
+        );
+        let layers = match texture.dimension() {
+            wgpu::TextureDimension::D3 => {
+                texture
+                    .size()
+                    .mip_level_size(self.mip_level, wgpu::TextureDimension::D3)
+                    .depth_or_array_layers
+            }
+            _ => texture.depth_or_array_layers(),
+        };
+        assert!(
+            self.layer < layers,
+            "Subresource: layer {} out of range for {layers} layers",
+            self.layer
+        );
+    }
+
+    /// View suitable for sampling. 3D textures keep their full depth at the
+    /// chosen mip so the slice can be picked in the shader.
+    pub fn source_view(
+        &self,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::TextureView {
+        self.validate(texture);
+        let (dimension, base_array_layer) = match texture.dimension() {
+            wgpu::TextureDimension::D3 => (wgpu::TextureViewDimension::D3, 0),
+            _ => (wgpu::TextureViewDimension::D2, self.layer),
+        };
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Subresource Source View"),
+            format: Some(format),
+            dimension: Some(dimension),
+            base_mip_level: self.mip_level,
+            mip_level_count: Some(1),
+            base_array_layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    /// Renderable view of a 2D texture layer. 3D slices cannot be render
+    /// attachments and have to go through a copy instead.
+    pub fn target_view(
+        &self,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::TextureView {
+        self.validate(texture);
+        assert_ne!(
+            texture.dimension(),
+            wgpu::TextureDimension::D3,
+            "Subresource: 3D slices are not renderable"
+        );
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Subresource Target View"),
+            format: Some(format),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: self.mip_level,
+            mip_level_count: Some(1),
+            base_array_layer: self.layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    /// Runs `render` against a renderable view of this subresource. For 3D
+    /// textures it renders into a scratch texture and copies it into the slice,
+    /// which requires `COPY_DST` on the destination.
+    pub fn render_into(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        render: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+    ) {
+        if texture.dimension() != wgpu::TextureDimension::D3 {
+            render(encoder, &self.target_view(texture, format));
+            return;
+        }
+
+        let extent = self.extent(texture);
+        let scratch = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Subresource 3D Slice Scratch"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: texture.format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[format],
+        });
+        render(
+            encoder,
+            &scratch.create_view(&wgpu::TextureViewDescriptor {
+                format: Some(format),
+                ..Default::default()
+            }),
+        );
+        encoder.copy_texture_to_texture(
+            Subresource::default().copy_target(&scratch),
+            self.copy_target(texture),
+            extent,
+        );
+    }
+
+    pub fn copy_target<'a>(&self, texture: &'a wgpu::Texture) -> wgpu::ImageCopyTexture<'a> {
+        self.validate(texture);
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: self.mip_level,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: self.layer,
+            },
+            aspect: wgpu::TextureAspect::All,
+        }
+    }
+
+    /// Normalised depth coordinate of the slice centre for 3D sampling.
+    pub fn slice_coord(&self, texture: &wgpu::Texture) -> f32 {
+        match texture.dimension() {
+            wgpu::TextureDimension::D3 => {
+                let depth = texture
+                    .size()
+                    .mip_level_size(self.mip_level, wgpu::TextureDimension::D3)
+                    .depth_or_array_layers;
+                (self.layer as f32 + 0.5) / depth as f32
+            }
+            _ => 0.,
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Images bigger than `max_texture_dimension_2d`, split across several
+//! textures and drawn by `blitter_new::Blitter::blit_tiled`.
+
+use crate::blit_options::Orientation;
+
+/// Texels each tile repeats from its neighbours on every side, which is as
+/// far as bilinear filtering reaches.
+const BORDER: u32 = 1;
+
+#[derive(Debug)]
+pub struct TiledTexture {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub(crate) tiles: Vec<Tile>,
+}
+
+/// Pixels of the image, as opposed to texels of a tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Region {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+#[derive(Debug)]
+pub(crate) struct Tile {
+    pub(crate) texture: wgpu::Texture,
+    pub(crate) view: wgpu::TextureView,
+    /// The pixels this tile draws; the texture holds a border around them.
+    pub(crate) core: Region,
+    /// Image pixel of the texture's first texel.
+    pub(crate) origin: (u32, u32),
+}
+
+/// Mirrors `Tile` in `blit_new.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct TileParams {
+    offset: [f32; 2],
+    scale: [f32; 2],
+    orientation: u32,
+    _padding: u32,
+}
+
+impl Tile {
+    pub(crate) fn params(&self, orientation: Orientation) -> TileParams {
+        TileParams::new(self.core, self.origin, self.texture.size(), orientation)
+    }
+}
+
+impl TileParams {
+    fn new(
+        core: Region,
+        origin: (u32, u32),
+        size: wgpu::Extent3d,
+        orientation: Orientation,
+    ) -> Self {
+        let (width, height) = (size.width as f32, size.height as f32);
+        Self {
+            offset: [
+                (core.x - origin.0) as f32 / width,
+                (core.y - origin.1) as f32 / height,
+            ],
+            scale: [core.width as f32 / width, core.height as f32 / height],
+            orientation: orientation as u32,
+            _padding: 0,
+        }
+    }
+}
+
+/// Splits `length` into runs of at most `max` once each gets its borders.
+fn spans(length: u32, max: u32) -> Vec<(u32, u32)> {
+    if length <= max {
+        return vec![(0, length)];
+    }
+    let step = max - 2 * BORDER;
+    (0..length.div_ceil(step))
+        .map(|i| (i * step, step.min(length - i * step)))
+        .collect()
+}
+
+/// Each tile's core, the image pixel its texture starts at and the texture
+/// size, row by row.
+fn layout(width: u32, height: u32, max: u32) -> Vec<(Region, (u32, u32), wgpu::Extent3d)> {
+    let mut tiles = Vec::new();
+    for (y, core_height) in spans(height, max) {
+        for &(x, core_width) in &spans(width, max) {
+            let core = Region {
+                x,
+                y,
+                width: core_width,
+                height: core_height,
+            };
+            let origin = (x.saturating_sub(BORDER), y.saturating_sub(BORDER));
+            let size = wgpu::Extent3d {
+                width: (x + core_width + BORDER).min(width) - origin.0,
+                height: (y + core_height + BORDER).min(height) - origin.1,
+                depth_or_array_layers: 1,
+            };
+            tiles.push((core, origin, size));
+        }
+    }
+    tiles
+}
+
+impl TiledTexture {
+    /// Like `create_texture_with_data` for a single-level 2D `desc` of any
+    /// size. Images that fit become one tile with no border.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        desc: &wgpu::TextureDescriptor,
+        data: &[u8],
+    ) -> Self {
+        let tiled = Self::allocate(device, desc);
+        let bytes_per_pixel = tiled.bytes_per_pixel();
+        for tile in &tiled.tiles {
+            queue.write_texture(
+                tile.texture.as_image_copy(),
+                data,
+                wgpu::ImageDataLayout {
+                    offset: tiled.row_offset(tile, 0),
+                    bytes_per_row: Some(tiled.width * bytes_per_pixel),
+                    rows_per_image: None,
+                },
+                tile.texture.size(),
+            );
+        }
+        tiled
+    }
+
+    /// Creates the tiles, with `COPY_DST` added, without filling them.
+    pub fn allocate(device: &wgpu::Device, desc: &wgpu::TextureDescriptor) -> Self {
+        assert!(
+            desc.dimension == wgpu::TextureDimension::D2
+                && desc.size.depth_or_array_layers == 1
+                && desc.mip_level_count == 1
+                && desc.sample_count == 1,
+            "TiledTexture: only single-level 2D textures can be tiled"
+        );
+        assert!(
+            desc.format.block_size(None).is_some() && !desc.format.is_compressed(),
+            "TiledTexture: {:?} can't be tiled",
+            desc.format
+        );
+        let (width, height) = (desc.size.width, desc.size.height);
+        let max = device.limits().max_texture_dimension_2d;
+
+        let tiles: Vec<Tile> = layout(width, height, max)
+            .into_iter()
+            .map(|(core, origin, size)| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    size,
+                    usage: desc.usage | wgpu::TextureUsages::COPY_DST,
+                    ..*desc
+                });
+                Tile {
+                    view: texture.create_view(&Default::default()),
+                    texture,
+                    core,
+                    origin,
+                }
+            })
+            .collect();
+        log::debug!(
+            "Split {width}x{height} {:?} into {} tiles",
+            desc.format,
+            tiles.len()
+        );
+
+        Self {
+            width,
+            height,
+            format: desc.format,
+            tiles,
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub(crate) fn bytes_per_pixel(&self) -> u32 {
+        self.format.block_size(None).unwrap()
+    }
+
+    /// Where `row` of `tile` starts in tightly packed image data.
+    pub(crate) fn row_offset(&self, tile: &Tile, row: u32) -> u64 {
+        let (x, y) = tile.origin;
+        ((y + row) as u64 * self.width as u64 + x as u64) * self.bytes_per_pixel() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: u32, y: u32, width: u32, height: u32) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    fn params((core, origin, size): (Region, (u32, u32), wgpu::Extent3d)) -> TileParams {
+        TileParams::new(core, origin, size, Orientation::Normal)
+    }
+
+    #[test]
+    fn exact_multiple_of_tile_size() {
+        // Cores of 4 leave room for a border on both sides of a 6-texel tile.
+        assert_eq!(spans(12, 6), [(0, 4), (4, 4), (8, 4)]);
+        let tiles = layout(12, 3, 6);
+        assert_eq!(
+            tiles,
+            [
+                (region(0, 0, 4, 3), (0, 0), extent(5, 3)),
+                (region(4, 0, 4, 3), (3, 0), extent(6, 3)),
+                (region(8, 0, 4, 3), (7, 0), extent(5, 3)),
+            ]
+        );
+        let middle = params(tiles[1]);
+        assert_eq!(middle.offset, [1. / 6., 0.]);
+        assert_eq!(middle.scale, [4. / 6., 1.]);
+        let last = params(tiles[2]);
+        assert_eq!(last.offset, [1. / 5., 0.]);
+        assert_eq!(last.scale, [4. / 5., 1.]);
+    }
+
+    #[test]
+    fn ragged_last_tile() {
+        assert_eq!(spans(10, 6), [(0, 4), (4, 4), (8, 2)]);
+        assert_eq!(spans(7, 6), [(0, 4), (4, 3)]);
+        let tiles = layout(10, 7, 6);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[2], (region(8, 0, 2, 4), (7, 0), extent(3, 5)));
+        assert_eq!(tiles[5], (region(8, 4, 2, 3), (7, 3), extent(3, 4)));
+        let corner = params(tiles[5]);
+        assert_eq!(corner.offset, [1. / 3., 1. / 4.]);
+        assert_eq!(corner.scale, [2. / 3., 3. / 4.]);
+    }
+
+    #[test]
+    fn smaller_than_one_tile() {
+        assert_eq!(spans(6, 6), [(0, 6)]);
+        let tiles = layout(5, 3, 6);
+        assert_eq!(tiles, [(region(0, 0, 5, 3), (0, 0), extent(5, 3))]);
+        let only = params(tiles[0]);
+        assert_eq!(only.offset, [0., 0.]);
+        assert_eq!(only.scale, [1., 1.]);
+    }
+
+    #[test]
+    fn cores_cover_the_image_once_with_borders() {
+        for (width, height, max) in [(1, 1, 3), (13, 4, 3), (17, 9, 8), (64, 65, 16)] {
+            let mut covered = vec![0; (width * height) as usize];
+            for (core, origin, size) in layout(width, height, max) {
+                assert!(size.width <= max && size.height <= max);
+                for y in core.y..core.y + core.height {
+                    for x in core.x..core.x + core.width {
+                        covered[(y * width + x) as usize] += 1;
+                    }
+                }
+                // Filtering at the core's edge reads one texel further,
+                // unless that's past the image's edge.
+                let end = (origin.0 + size.width, origin.1 + size.height);
+                assert_eq!(origin.0, core.x.saturating_sub(BORDER));
+                assert_eq!(origin.1, core.y.saturating_sub(BORDER));
+                assert_eq!(end.0, (core.x + core.width + BORDER).min(width));
+                assert_eq!(end.1, (core.y + core.height + BORDER).min(height));
+            }
+            assert!(covered.iter().all(|&count| count == 1), "{width}x{height}");
+        }
+    }
+
+    #[test]
+    fn new_splits_by_device_limit() {
+        let limits = wgpu::Limits {
+            max_texture_dimension_2d: 8,
+            ..Default::default()
+        };
+        let Some((device, queue)) = crate::golden::headless_device_with_limits(limits) else {
+            eprintln!("No adapter, skipping the tile split");
+            return;
+        };
+
+        let (width, height) = (20, 7);
+        let data: Vec<u8> = (0..width * height * 4).map(|i| i as u8).collect();
+        let tiled = TiledTexture::new(
+            &device,
+            &queue,
+            &wgpu::TextureDescriptor {
+                label: None,
+                size: extent(width, height),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            &data,
+        );
+        // Cores of 6 across, one row.
+        assert_eq!(tiled.tile_count(), 4);
+        for (tile, (core, origin, size)) in tiled.tiles.iter().zip(layout(width, height, 8)) {
+            assert_eq!((tile.core, tile.origin), (core, origin));
+            assert_eq!(tile.texture.size(), size);
+        }
+        let last = &tiled.tiles[3];
+        assert_eq!(last.core, region(18, 0, 2, 7));
+        assert_eq!(tiled.row_offset(last, 2), ((2 * width + 17) * 4) as u64);
+    }
+}
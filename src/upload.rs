@@ -0,0 +1,550 @@
+//! Decodes images on a worker thread and streams them to the GPU a bounded
+//! number of bytes per frame, so loading never stalls rendering.
+
+use std::{
+    num::NonZeroU64,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::mpsc::{self, TryRecvError},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use wgpu::util::{DeviceExt, StagingBelt};
+
+use crate::{
+    loader::{self, HdrStorage, LoadedImage},
+    tiled::TiledTexture,
+};
+
+/// Refers to one image given to [`UploadQueue::load`], until it's taken.
+/// Handles are never reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadHandle(u64);
+
+pub enum UploadState<'a> {
+    /// Still decoding, or streaming with `progress` of the rows staged.
+    Pending {
+        progress: f32,
+    },
+    /// `image.data` has been released; the texels are in `texture`.
+    Ready {
+        image: &'a LoadedImage,
+        texture: &'a TiledTexture,
+    },
+    Failed(&'a anyhow::Error),
+}
+
+struct DecodeRequest {
+    path: PathBuf,
+    hdr_storage: HdrStorage,
+    result: mpsc::Sender<Result<LoadedImage>>,
+}
+
+/// Decodes requests in order until the queue is dropped. A panicking decoder
+/// fails only its own image.
+fn spawn_decoder(features: wgpu::Features) -> mpsc::Sender<DecodeRequest> {
+    let (sender, requests) = mpsc::channel::<DecodeRequest>();
+    thread::Builder::new()
+        .name("Image Decoder".into())
+        .spawn(move || {
+            for request in requests {
+                let path = request.path;
+                let decoded = panic::catch_unwind(AssertUnwindSafe(|| {
+                    loader::load(&path, request.hdr_storage, features)
+                }))
+                .unwrap_or_else(|_| Err(anyhow!("Decoder panicked on {}", path.display())));
+                let _ = request.result.send(decoded);
+            }
+        })
+        .expect("Failed to spawn the image decoder thread");
+    sender
+}
+
+enum Job {
+    Decoding {
+        receiver: mpsc::Receiver<Result<LoadedImage>>,
+        label: String,
+        usage: wgpu::TextureUsages,
+    },
+    Streaming(Stream),
+    Failed(anyhow::Error),
+}
+
+/// Next row to stage, by tile. Finished once `tile` is past the last one.
+struct Stream {
+    image: LoadedImage,
+    texture: TiledTexture,
+    tile: usize,
+    row: u32,
+    rows_staged: u32,
+}
+
+impl Stream {
+    fn is_finished(&self) -> bool {
+        self.tile == self.texture.tiles.len()
+    }
+}
+
+pub struct UploadQueue {
+    /// In load order, which is also handle order.
+    jobs: Vec<(UploadHandle, Job)>,
+    next_handle: u64,
+    belt: StagingBelt,
+    /// Rows are staged here with padded strides, then copied into tiles.
+    rows: wgpu::Buffer,
+    bytes_per_frame: u64,
+    decoder: mpsc::Sender<DecodeRequest>,
+    placeholder: wgpu::TextureView,
+}
+
+impl UploadQueue {
+    /// `bytes_per_frame` bounds how much [`Self::process`] stages per call,
+    /// though a single row always goes through.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, bytes_per_frame: u64) -> Self {
+        // Widest possible row: a full-size tile of Rgba32Float.
+        let max_row = wgpu::util::align_to(
+            device.limits().max_texture_dimension_2d as u64 * 16,
+            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+        );
+        let rows = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Upload Rows"),
+            size: bytes_per_frame.max(max_row),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let placeholder = device
+            .create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some("Upload Placeholder"),
+                    size: wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                &[64, 64, 64, 255],
+            )
+            .create_view(&Default::default());
+        Self {
+            jobs: Vec::new(),
+            next_handle: 0,
+            belt: StagingBelt::new(bytes_per_frame),
+            rows,
+            bytes_per_frame,
+            decoder: spawn_decoder(device.features()),
+            placeholder,
+        }
+    }
+
+    /// Queues `path` for decoding on the worker thread; see [`loader::load`].
+    pub fn load(
+        &mut self,
+        path: impl Into<PathBuf>,
+        hdr_storage: HdrStorage,
+        usage: wgpu::TextureUsages,
+    ) -> UploadHandle {
+        let path = path.into();
+        let label = path.display().to_string();
+        let (result, receiver) = mpsc::channel();
+        // If the worker is gone, the dropped request fails the job.
+        let _ = self.decoder.send(DecodeRequest {
+            path,
+            hdr_storage,
+            result,
+        });
+        let handle = UploadHandle(self.next_handle);
+        self.next_handle += 1;
+        self.jobs.push((
+            handle,
+            Job::Decoding {
+                receiver,
+                label,
+                usage,
+            },
+        ));
+        handle
+    }
+
+    fn index(&self, handle: UploadHandle) -> usize {
+        self.jobs
+            .binary_search_by_key(&handle, |(handle, _)| *handle)
+            .unwrap_or_else(|_| panic!("{handle:?} was already taken"))
+    }
+
+    pub fn state(&self, handle: UploadHandle) -> UploadState<'_> {
+        match &self.jobs[self.index(handle)].1 {
+            Job::Decoding { .. } => UploadState::Pending { progress: 0. },
+            Job::Streaming(stream) if stream.is_finished() => UploadState::Ready {
+                image: &stream.image,
+                texture: &stream.texture,
+            },
+            Job::Streaming(stream) => {
+                let total: u32 = stream
+                    .texture
+                    .tiles
+                    .iter()
+                    .map(|tile| tile.texture.height())
+                    .sum();
+                UploadState::Pending {
+                    progress: stream.rows_staged as f32 / total as f32,
+                }
+            }
+            Job::Failed(error) => UploadState::Failed(error),
+        }
+    }
+
+    /// Removes a finished job, handing over the image and its texture, or
+    /// `None` while it's pending. `handle` is invalid afterwards.
+    pub fn take(&mut self, handle: UploadHandle) -> Option<Result<(LoadedImage, TiledTexture)>> {
+        let index = self.index(handle);
+        match &self.jobs[index].1 {
+            Job::Streaming(stream) if stream.is_finished() => {}
+            Job::Failed(_) => {}
+            _ => return None,
+        }
+        Some(match self.jobs.remove(index).1 {
+            Job::Streaming(stream) => Ok((stream.image, stream.texture)),
+            Job::Failed(error) => Err(error),
+            Job::Decoding { .. } => unreachable!(),
+        })
+    }
+
+    /// Drops a job whatever its state, e.g. when the image is no longer
+    /// wanted. `handle` is invalid afterwards.
+    pub fn remove(&mut self, handle: UploadHandle) {
+        let index = self.index(handle);
+        self.jobs.remove(index);
+    }
+
+    /// Grey, for drawing in place of images that aren't ready.
+    pub fn placeholder(&self) -> &wgpu::TextureView {
+        &self.placeholder
+    }
+
+    /// Picks up decoded images and records the copies for this frame's share
+    /// of rows into `encoder`. Call [`Self::finish`] before submitting it and
+    /// [`Self::recall`] after.
+    pub fn process(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        for (_, job) in &mut self.jobs {
+            let Job::Decoding {
+                receiver,
+                label,
+                usage,
+            } = job
+            else {
+                continue;
+            };
+            let decoded = match receiver.try_recv() {
+                Ok(decoded) => decoded,
+                Err(TryRecvError::Empty) => continue,
+                Err(TryRecvError::Disconnected) => Err(anyhow!("Decoder thread panicked")),
+            };
+            *job = match decoded {
+                Ok(image) => Job::Streaming(Stream {
+                    texture: image.allocate_tiled_texture(device, Some(label), *usage),
+                    image,
+                    tile: 0,
+                    row: 0,
+                    rows_staged: 0,
+                }),
+                Err(error) => {
+                    log::error!("Failed to load {label}: {error:#}");
+                    Job::Failed(error)
+                }
+            };
+        }
+
+        let mut offset = 0;
+        for (_, job) in &mut self.jobs {
+            let Job::Streaming(stream) = job else {
+                continue;
+            };
+            if stream.is_finished() {
+                continue;
+            }
+            let Stream {
+                image,
+                texture,
+                tile,
+                row,
+                rows_staged,
+            } = stream;
+            let bytes_per_pixel = texture.bytes_per_pixel();
+            while let Some(current) = texture.tiles.get(*tile) {
+                let size = current.texture.size();
+                let row_size = size.width * bytes_per_pixel;
+                let padded_row =
+                    wgpu::util::align_to(row_size, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) as u64;
+                let budget = self.bytes_per_frame.saturating_sub(offset) / padded_row;
+                let room = (self.rows.size() - offset) / padded_row;
+                let count = match (offset, budget.min(room)) {
+                    // A row wider than the budget still has to go through.
+                    (0, 0) => 1,
+                    (_, 0) => break,
+                    (_, rows) => rows.min((size.height - *row) as u64) as u32,
+                };
+
+                {
+                    let mut staged = self.belt.write_buffer(
+                        encoder,
+                        &self.rows,
+                        offset,
+                        NonZeroU64::new(count as u64 * padded_row).unwrap(),
+                        device,
+                    );
+                    for i in 0..count {
+                        let src = texture.row_offset(current, *row + i) as usize;
+                        let dst = (i as u64 * padded_row) as usize;
+                        staged[dst..dst + row_size as usize]
+                            .copy_from_slice(&image.data[src..src + row_size as usize]);
+                    }
+                }
+                encoder.copy_buffer_to_texture(
+                    wgpu::ImageCopyBuffer {
+                        buffer: &self.rows,
+                        layout: wgpu::ImageDataLayout {
+                            offset,
+                            bytes_per_row: Some(padded_row as u32),
+                            rows_per_image: None,
+                        },
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &current.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: *row,
+                            z: 0,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width: size.width,
+                        height: count,
+                        depth_or_array_layers: 1,
+                    },
+                );
+                offset += count as u64 * padded_row;
+                *row += count;
+                *rows_staged += count;
+                if *row == size.height {
+                    *tile += 1;
+                    *row = 0;
+                }
+            }
+
+            if *tile < texture.tiles.len() {
+                // Out of budget for this frame.
+                break;
+            }
+            image.data = Vec::new();
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// A device whose textures are at most 8 texels wide, so small images
+    /// still tile, or `None` without an adapter.
+    fn small_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        crate::golden::headless_device_with_limits(wgpu::Limits {
+            max_texture_dimension_2d: 8,
+            ..Default::default()
+        })
+    }
+
+    fn frame(device: &wgpu::Device, queue: &wgpu::Queue, uploads: &mut UploadQueue) {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        uploads.process(device, &mut encoder);
+        uploads.finish();
+        queue.submit([encoder.finish()]);
+        uploads.recall();
+    }
+
+    /// Runs frames until `handle` has left decoding, returning its progress
+    /// after each frame until it's no longer pending.
+    fn progress(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uploads: &mut UploadQueue,
+        handle: UploadHandle,
+    ) -> Vec<f32> {
+        let start = Instant::now();
+        let mut progress = Vec::new();
+        loop {
+            frame(device, queue, uploads);
+            match uploads.state(handle) {
+                UploadState::Pending { progress: 0. } if progress.is_empty() => {
+                    assert!(start.elapsed() < Duration::from_secs(10), "Decoding hung");
+                    thread::sleep(Duration::from_millis(1));
+                }
+                UploadState::Pending { progress: p } => progress.push(p),
+                _ => return progress,
+            }
+        }
+    }
+
+    fn write_png(name: &str, width: u32, height: u32) -> (PathBuf, Vec<u8>) {
+        let path = std::env::temp_dir().join(format!("upload-{}-{name}.png", std::process::id()));
+        let data: Vec<u8> = (0..width * height * 4).map(|i| (i * 7) as u8).collect();
+        image::save_buffer(&path, &data, width, height, image::ColorType::Rgba8).unwrap();
+        (path, data)
+    }
+
+    /// Reads back a tile's `Rgba8` texels, tightly packed.
+    fn read_tile(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Vec<u8> {
+        let size = texture.size();
+        let padded_row = wgpu::util::align_to(size.width * 4, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_row * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        queue.submit([encoder.finish()]);
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks(padded_row as usize)
+            .flat_map(|row| &row[..size.width as usize * 4])
+            .copied()
+            .collect();
+        data
+    }
+
+    #[test]
+    fn budget_splits_rows_across_frames_and_tiles() {
+        let Some((device, queue)) = small_device() else {
+            eprintln!("No adapter, skipping the upload");
+            return;
+        };
+        // Four tiles of 7 rows, each row padded to 256 bytes.
+        let (path, data) = write_png("budget", 20, 7);
+        let mut uploads = UploadQueue::new(&device, &queue, 4 * 256);
+        let handle = uploads.load(&path, HdrStorage::default(), wgpu::TextureUsages::COPY_SRC);
+        let progress = progress(&device, &queue, &mut uploads, handle);
+        std::fs::remove_file(path).unwrap();
+
+        // Four rows a frame, carrying on into the next tile, for 28 rows.
+        let expected: Vec<f32> = (1..7).map(|frame| (frame * 4) as f32 / 28.).collect();
+        assert_eq!(progress, expected);
+        let UploadState::Ready { image, texture } = uploads.state(handle) else {
+            panic!("Upload didn't finish");
+        };
+        assert!(image.data.is_empty());
+        assert_eq!(texture.tile_count(), 4);
+        let last = &texture.tiles[3];
+        let (x, width) = (last.origin.0 as usize, last.texture.width() as usize);
+        let expected: Vec<u8> = data
+            .chunks(20 * 4)
+            .flat_map(|row| &row[x * 4..(x + width) * 4])
+            .copied()
+            .collect();
+        assert_eq!(read_tile(&device, &queue, &last.texture), expected);
+    }
+
+    #[test]
+    fn rows_wider_than_the_budget_go_one_per_frame() {
+        let Some((device, queue)) = small_device() else {
+            eprintln!("No adapter, skipping the upload");
+            return;
+        };
+        let (path, _) = write_png("narrow", 8, 5);
+        let mut uploads = UploadQueue::new(&device, &queue, 100);
+        let handle = uploads.load(&path, HdrStorage::default(), wgpu::TextureUsages::COPY_SRC);
+        let progress = progress(&device, &queue, &mut uploads, handle);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(progress, [0.2, 0.4, 0.6, 0.8]);
+        assert!(matches!(uploads.state(handle), UploadState::Ready { .. }));
+    }
+
+    #[test]
+    fn unreadable_images_fail() {
+        let Some((device, queue)) = small_device() else {
+            eprintln!("No adapter, skipping the upload");
+            return;
+        };
+        let (path, _) = write_png("ok", 4, 4);
+        let mut uploads = UploadQueue::new(&device, &queue, 1 << 20);
+        let missing = uploads.load(
+            std::env::temp_dir().join("upload-missing.png"),
+            HdrStorage::default(),
+            wgpu::TextureUsages::COPY_SRC,
+        );
+        let ok = uploads.load(&path, HdrStorage::default(), wgpu::TextureUsages::COPY_SRC);
+        // The worker decodes in order, so `missing` has failed by now.
+        assert!(progress(&device, &queue, &mut uploads, ok).is_empty());
+        std::fs::remove_file(path).unwrap();
+
+        assert!(matches!(uploads.state(missing), UploadState::Failed(_)));
+        assert!(matches!(uploads.state(ok), UploadState::Ready { .. }));
+    }
+
+    #[test]
+    fn taken_jobs_are_dropped() {
+        let Some((device, queue)) = small_device() else {
+            eprintln!("No adapter, skipping the upload");
+            return;
+        };
+        let (path, data) = write_png("take", 4, 4);
+        let mut uploads = UploadQueue::new(&device, &queue, 1 << 20);
+        let missing = uploads.load(
+            std::env::temp_dir().join("upload-missing.png"),
+            HdrStorage::default(),
+            wgpu::TextureUsages::COPY_SRC,
+        );
+        let ok = uploads.load(&path, HdrStorage::default(), wgpu::TextureUsages::COPY_SRC);
+        let dropped = uploads.load(&path, HdrStorage::default(), wgpu::TextureUsages::COPY_SRC);
+        assert!(uploads.take(ok).is_none());
+        uploads.remove(dropped);
+        progress(&device, &queue, &mut uploads, ok);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(uploads.take(missing).unwrap().is_err());
+        let (image, texture) = uploads.take(ok).unwrap().unwrap();
+        assert!(image.data.is_empty());
+        assert_eq!(read_tile(&device, &queue, &texture.tiles[0].texture), data);
+        assert!(uploads.jobs.is_empty());
+        // Handles stay unique after their jobs are gone.
+        let next = uploads.load("", HdrStorage::default(), wgpu::TextureUsages::COPY_SRC);
+        assert!(![missing, ok, dropped].contains(&next));
+    }
+}
@@ -0,0 +1,394 @@
+use std::sync::mpsc;
+
+use anyhow::{Context, Result};
+
+use crate::blitter_old::ColourSpace;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum YuvMatrix {
+    Bt601,
+    #[default]
+    Bt709,
+    Bt2020,
+}
+
+impl YuvMatrix {
+    /// Luma weights of red and blue.
+    pub fn kr_kb(self) -> (f32, f32) {
+        match self {
+            YuvMatrix::Bt601 => (0.299, 0.114),
+            YuvMatrix::Bt709 => (0.2126, 0.0722),
+            YuvMatrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum YuvRange {
+    /// 16..=235 luma, 16..=240 chroma.
+    #[default]
+    Limited,
+    Full,
+}
+
+/// Where 4:2:0 chroma samples sit relative to luma.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ChromaSiting {
+    /// Between luma samples in both directions (MPEG-1, JPEG).
+    Center,
+    /// Co-sited horizontally, centred vertically (MPEG-2, H.264).
+    #[default]
+    Left,
+    /// Co-sited in both directions (BT.2020).
+    TopLeft,
+}
+
+impl ChromaSiting {
+    fn cosited(self) -> [f32; 2] {
+        match self {
+            ChromaSiting::Center => [0., 0.],
+            ChromaSiting::Left => [1., 0.],
+            ChromaSiting::TopLeft => [1., 1.],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct YuvFormat {
+    pub matrix: YuvMatrix,
+    pub range: YuvRange,
+    pub siting: ChromaSiting,
+    /// Transfer and primaries of the R'G'B' values the matrix produces.
+    pub space: ColourSpace,
+}
+
+impl Default for YuvFormat {
+    fn default() -> Self {
+        Self {
+            matrix: YuvMatrix::default(),
+            range: YuvRange::default(),
+            siting: ChromaSiting::default(),
+            space: ColourSpace::Srgb,
+        }
+    }
+}
+
+/// How RGB is downsampled to 4:2:0 chroma when encoding.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ChromaFilter {
+    /// Takes the luma texel the chroma sample is sited on.
+    Point,
+    /// Averages the covered texels: a box on centred axes, a 1-2-1 tent on
+    /// co-sited ones.
+    #[default]
+    Linear,
+}
+
+impl YuvFormat {
+    pub(crate) fn encode_params(&self, filter: ChromaFilter) -> YuvParams {
+        YuvParams {
+            chroma_filter: filter as u32,
+            ..self.params()
+        }
+    }
+
+    pub(crate) fn params(&self) -> YuvParams {
+        let (kr, kb) = self.matrix.kr_kb();
+        let (y_offset, y_scale, c_scale) = match self.range {
+            YuvRange::Limited => (16. / 255., 255. / 219., 255. / 224.),
+            YuvRange::Full => (0., 1., 1.),
+        };
+        YuvParams {
+            kr,
+            kb,
+            y_offset,
+            y_scale,
+            c_scale,
+            space: self.space as u32,
+            cosited: self.siting.cosited(),
+            chroma_filter: 0,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Planes of a 4:2:0 frame. Chroma planes are half the luma size.
+#[derive(Copy, Clone, Debug)]
+pub enum YuvPlanes<'a> {
+    /// `R8Unorm` luma and interleaved `Rg8Unorm` chroma.
+    Nv12 {
+        y: &'a wgpu::TextureView,
+        uv: &'a wgpu::TextureView,
+    },
+    /// Three `R8Unorm` planes.
+    I420 {
+        y: &'a wgpu::TextureView,
+        u: &'a wgpu::TextureView,
+        v: &'a wgpu::TextureView,
+    },
+}
+
+/// Mirrors `YuvParams` in `blit_new.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct YuvParams {
+    kr: f32,
+    kb: f32,
+    y_offset: f32,
+    y_scale: f32,
+    c_scale: f32,
+    space: u32,
+    cosited: [f32; 2],
+    chroma_filter: u32,
+    _padding: [u32; 3],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum YuvLayout {
+    Nv12,
+    I420,
+}
+
+/// 4:2:0 planes rendered by `blitter_new::Blitter::encode_yuv`, or written
+/// with `Queue::write_texture`, plus a staging buffer to read them back for a
+/// software encoder.
+#[derive(Debug)]
+pub struct YuvFrame {
+    pub width: u32,
+    pub height: u32,
+    pub layout: YuvLayout,
+    pub(crate) planes: Vec<Plane>,
+    staging: wgpu::Buffer,
+}
+
+#[derive(Debug)]
+pub(crate) struct Plane {
+    texture: wgpu::Texture,
+    pub(crate) view: wgpu::TextureView,
+    pub(crate) format: wgpu::TextureFormat,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    bytes_per_pixel: u32,
+    offset: u64,
+}
+
+impl Plane {
+    fn padded_row(&self) -> u32 {
+        wgpu::util::align_to(
+            self.width * self.bytes_per_pixel,
+            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+        )
+    }
+}
+
+impl YuvFrame {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, layout: YuvLayout) -> Self {
+        assert!(
+            width % 2 == 0 && height % 2 == 0,
+            "4:2:0 frames need even dimensions, got {width}x{height}"
+        );
+        let (chroma_width, chroma_height) = (width / 2, height / 2);
+        let plane_sizes: &[(_, _, _, _)] = match layout {
+            YuvLayout::Nv12 => &[
+                ("Y Plane", width, height, wgpu::TextureFormat::R8Unorm),
+                (
+                    "UV Plane",
+                    chroma_width,
+                    chroma_height,
+                    wgpu::TextureFormat::Rg8Unorm,
+                ),
+            ],
+            YuvLayout::I420 => &[
+                ("Y Plane", width, height, wgpu::TextureFormat::R8Unorm),
+                (
+                    "U Plane",
+                    chroma_width,
+                    chroma_height,
+                    wgpu::TextureFormat::R8Unorm,
+                ),
+                (
+                    "V Plane",
+                    chroma_width,
+                    chroma_height,
+                    wgpu::TextureFormat::R8Unorm,
+                ),
+            ],
+        };
+
+        let mut offset = 0;
+        let planes: Vec<Plane> = plane_sizes
+            .iter()
+            .map(|&(label, width, height, format)| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let plane = Plane {
+                    view: texture.create_view(&Default::default()),
+                    texture,
+                    format,
+                    width,
+                    height,
+                    bytes_per_pixel: format.block_size(None).unwrap(),
+                    offset,
+                };
+                offset += plane.padded_row() as u64 * height as u64;
+                plane
+            })
+            .collect();
+
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("YUV Readback"),
+            size: offset,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Self {
+            width,
+            height,
+            layout,
+            planes,
+            staging,
+        }
+    }
+
+    /// The planes as a blit source, e.g. to preview what was encoded.
+    pub fn planes(&self) -> YuvPlanes<'_> {
+        match self.layout {
+            YuvLayout::Nv12 => YuvPlanes::Nv12 {
+                y: &self.planes[0].view,
+                uv: &self.planes[1].view,
+            },
+            YuvLayout::I420 => YuvPlanes::I420 {
+                y: &self.planes[0].view,
+                u: &self.planes[1].view,
+                v: &self.planes[2].view,
+            },
+        }
+    }
+
+    /// Size of the tightly packed frame returned by [`Self::read`].
+    pub fn packed_size(&self) -> usize {
+        self.width as usize * self.height as usize * 3 / 2
+    }
+
+    /// Records copies of every plane into the staging buffer.
+    pub fn copy_to_staging(&self, encoder: &mut wgpu::CommandEncoder) {
+        for plane in &self.planes {
+            encoder.copy_texture_to_buffer(
+                plane.texture.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &self.staging,
+                    layout: wgpu::ImageDataLayout {
+                        offset: plane.offset,
+                        bytes_per_row: Some(plane.padded_row()),
+                        rows_per_image: None,
+                    },
+                },
+                plane.texture.size(),
+            );
+        }
+    }
+
+    /// Waits for the staging copy and returns the planes back to back with
+    /// no row padding, the layout encoders take for NV12 and I420.
+    pub fn read(&self, device: &wgpu::Device) -> Result<Vec<u8>> {
+        let slice = self.staging.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .context("YUV readback was dropped")?
+            .context("Failed to map YUV readback")?;
+
+        let mut packed = Vec::with_capacity(self.packed_size());
+        {
+            let mapped = slice.get_mapped_range();
+            for plane in &self.planes {
+                let row = (plane.width * plane.bytes_per_pixel) as usize;
+                let padded_row = plane.padded_row() as usize;
+                let start = plane.offset as usize;
+                for y in 0..plane.height as usize {
+                    let offset = start + y * padded_row;
+                    packed.extend_from_slice(&mapped[offset..offset + row]);
+                }
+            }
+        }
+        self.staging.unmap();
+        Ok(packed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes distinct bytes to every plane, reads the frame back and
+    /// returns what was written and what was read.
+    fn round_trip(layout: YuvLayout) -> Option<(Vec<u8>, Vec<u8>)> {
+        let Some((device, queue)) = crate::golden::headless_device() else {
+            eprintln!("No adapter, skipping the {layout:?} readback");
+            return None;
+        };
+        // Rows far narrower than the 256-byte copy alignment.
+        let frame = YuvFrame::new(&device, 10, 6, layout);
+        let mut written = Vec::new();
+        for plane in &frame.planes {
+            let row = plane.width * plane.bytes_per_pixel;
+            let data: Vec<u8> = (0..row * plane.height)
+                .map(|i| (written.len() as u32 + i * 3) as u8)
+                .collect();
+            queue.write_texture(
+                plane.texture.as_image_copy(),
+                &data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row),
+                    rows_per_image: None,
+                },
+                plane.texture.size(),
+            );
+            written.extend(data);
+        }
+        let mut encoder = device.create_command_encoder(&Default::default());
+        frame.copy_to_staging(&mut encoder);
+        queue.submit([encoder.finish()]);
+        let read = frame.read(&device).unwrap();
+        assert_eq!(read.len(), frame.packed_size());
+        Some((written, read))
+    }
+
+    #[test]
+    fn read_packs_nv12() {
+        if let Some((written, read)) = round_trip(YuvLayout::Nv12) {
+            // 10x6 luma, then 5x3 interleaved chroma pairs.
+            assert_eq!(written.len(), 60 + 30);
+            assert_eq!(read, written);
+        }
+    }
+
+    #[test]
+    fn read_packs_i420() {
+        if let Some((written, read)) = round_trip(YuvLayout::I420) {
+            // 10x6 luma, then 5x3 U and 5x3 V.
+            assert_eq!(written.len(), 60 + 15 + 15);
+            assert_eq!(read, written);
+        }
+    }
+}
@@ -0,0 +1,816 @@
+//! Renders blit configurations headlessly and compares them with
+//! `tests/golden/*.png`. Without an adapter the renders are skipped, but the
+//! goldens of cases with a CPU reference are still checked against it. Run
+//! with `UPDATE_GOLDEN=1` to regenerate them from the GPU.
+
+use std::{
+    cell::OnceCell,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use blittin_test::{
+    blit_options::{
+        BlitOptions, Dither, GamutMapping, LutInterpolation, LutStage, Orientation, Swizzle,
+        Tonemap,
+    },
+    blitter_new::{self, BlitTarget, ColourRamp, DepthVisualisation, IntegerBlit, ResolveFilter},
+    blitter_old::{self, ColourSpace},
+    color_adjust::ColorAdjust,
+    golden::{self, Tolerance},
+    lut::{CubeLut, Lut3d},
+    reference::{self, Conversion, Image},
+    rgbe,
+    subresource::Subresource,
+    tiled::TiledTexture,
+    yuv::{ChromaFilter, YuvFormat, YuvFrame, YuvLayout},
+};
+use wgpu::{util::DeviceExt, AddressMode, TextureFormat};
+
+const SOURCE_SIZE: u32 = 16;
+
+/// What a case draws the source with.
+#[derive(Copy, Clone)]
+enum Blit {
+    /// `blitter_old` for a source in the given space, which the CPU
+    /// reference mirrors with the conversion.
+    Old(ColourSpace, Conversion),
+    /// `blit_to_texture`.
+    New,
+    /// `blit_tiled`, on a device that splits the source into 3x3 tiles.
+    Tiled,
+    /// `blit_with_options`, given a LUT to grade with.
+    Options(fn(&Lut3d) -> BlitOptions),
+    /// `resolve_to_target` of the source drawn into a 4x target.
+    Resolve(ResolveFilter),
+    /// `visualise_depth` of a diagonal depth ramp instead of the source.
+    Depth(DepthVisualisation),
+    /// `blit_integer` of a grid of `R32Uint` ids instead of the source.
+    Integer(IntegerBlit),
+    /// `encode_yuv` into a frame, then `blit_yuv` back.
+    Yuv(YuvLayout),
+    /// `blit_subresource` from the second layer of an array into the second
+    /// mip of a scratch texture, then `blit_to_texture` from there.
+    Subresource,
+}
+
+struct Case {
+    name: &'static str,
+    blit: Blit,
+    size: (u32, u32),
+    format: TextureFormat,
+    viewport: (f32, f32, f32, f32),
+    max_abs: f32,
+}
+
+impl Case {
+    /// The source scaled up to a 32x32 `Rgba8UnormSrgb` target.
+    const fn new(name: &'static str, blit: Blit) -> Self {
+        Self {
+            name,
+            blit,
+            size: (32, 32),
+            format: TextureFormat::Rgba8UnormSrgb,
+            viewport: (0., 0., 32., 32.),
+            max_abs: 2. / 255.,
+        }
+    }
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "old_linear_to_srgb",
+        blit: Blit::Old(ColourSpace::Linear, Conversion::LinearToSrgb),
+        size: (40, 30),
+        format: TextureFormat::Rgba8Unorm,
+        viewport: (0., 0., 40., 30.),
+        // Filtering may round to 8 bits before the shader encodes, and the
+        // sRGB curve is steep near black.
+        max_abs: 4. / 255.,
+    },
+    Case {
+        name: "old_srgb_to_linear",
+        blit: Blit::Old(ColourSpace::Srgb, Conversion::SrgbToLinear),
+        size: (24, 24),
+        format: TextureFormat::Rgba8UnormSrgb,
+        viewport: (4., 4., 16., 16.),
+        max_abs: 2. / 255.,
+    },
+    Case {
+        name: "new_downscale",
+        blit: Blit::New,
+        size: (7, 5),
+        format: TextureFormat::Rgba8Unorm,
+        viewport: (0., 0., 7., 5.),
+        max_abs: 2. / 255.,
+    },
+    Case {
+        name: "new_viewport",
+        blit: Blit::New,
+        size: (32, 32),
+        format: TextureFormat::Rgba8Unorm,
+        // Fractional viewport origins are rounded differently between
+        // implementations, so the origin stays on whole pixels.
+        viewport: (4., 2., 20., 24.),
+        max_abs: 2. / 255.,
+    },
+    Case {
+        name: "tiled_seams",
+        blit: Blit::Tiled,
+        // Within the 8x8 limit that splits the source.
+        size: (8, 8),
+        format: TextureFormat::Rgba8Unorm,
+        viewport: (0., 0., 8., 8.),
+        max_abs: 2. / 255.,
+    },
+    Case::new(
+        "options_tonemap",
+        Blit::Options(|_| BlitOptions {
+            exposure: 2.,
+            tonemap: Tonemap::AcesFitted,
+            ..Default::default()
+        }),
+    ),
+    Case::new(
+        "options_gamut",
+        Blit::Options(|_| BlitOptions {
+            source_space: ColourSpace::Rec2020,
+            gamut_mapping: GamutMapping::Perceptual,
+            ..Default::default()
+        }),
+    ),
+    Case::new(
+        "options_lut",
+        Blit::Options(|lut| BlitOptions {
+            lut: Some(LutStage {
+                lut,
+                interpolation: LutInterpolation::Tetrahedral,
+                space: ColourSpace::Srgb,
+            }),
+            ..Default::default()
+        }),
+    ),
+    Case {
+        // Noise is only visible where the target quantises coarsely.
+        format: TextureFormat::Rgba8Unorm,
+        ..Case::new(
+            "options_dither",
+            Blit::Options(|_| BlitOptions {
+                exposure: -4.,
+                dither: Dither::BlueNoise,
+                ..Default::default()
+            }),
+        )
+    },
+    Case::new(
+        "options_color_adjust",
+        Blit::Options(|_| BlitOptions {
+            color_adjust: ColorAdjust::saturation(0.25),
+            ..Default::default()
+        }),
+    ),
+    Case::new(
+        "options_swizzle_orientation",
+        Blit::Options(|_| BlitOptions {
+            swizzle: Swizzle::BGRA,
+            orientation: Orientation::Rotate90,
+            ..Default::default()
+        }),
+    ),
+    Case::new("resolve_box", Blit::Resolve(ResolveFilter::Box)),
+    Case::new(
+        "depth_turbo",
+        Blit::Depth(DepthVisualisation {
+            near: 1.,
+            far: 4.,
+            reverse_z: false,
+            ramp: ColourRamp::Turbo,
+        }),
+    ),
+    Case::new("integer_hash", Blit::Integer(IntegerBlit::HashColour)),
+    Case::new("yuv_nv12", Blit::Yuv(YuvLayout::Nv12)),
+    Case::new("yuv_i420", Blit::Yuv(YuvLayout::I420)),
+    Case::new("subresource", Blit::Subresource),
+];
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Gradients in red and green over a blue checkerboard, so filtering and
+/// transfer curves both show.
+fn source_rgba8() -> Vec<u8> {
+    (0..SOURCE_SIZE)
+        .flat_map(|y| (0..SOURCE_SIZE).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            let checker = ((x / 4 + y / 4) % 2) as u8 * 255;
+            [(x * 17) as u8, (y * 17) as u8, checker, 255]
+        })
+        .collect()
+}
+
+fn source_descriptor(label: &str, layers: u32) -> wgpu::TextureDescriptor<'_> {
+    wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: SOURCE_SIZE,
+            height: SOURCE_SIZE,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    }
+}
+
+/// A 3x3x3 grade that mixes channels, so both the LUT and its
+/// interpolation show.
+fn grading_lut() -> CubeLut {
+    let mut data = Vec::new();
+    for b in 0..3 {
+        for g in 0..3 {
+            for r in 0..3 {
+                let [r, g, b] = [r, g, b].map(|v| v as f32 / 2.);
+                data.push([(g + b) / 2., r * r, 1. - b]);
+            }
+        }
+    }
+    CubeLut {
+        title: None,
+        size: 3,
+        domain_min: [0.; 3],
+        domain_max: [1.; 3],
+        data,
+    }
+}
+
+/// Draws a depth ramp from 0 at the top left to 1 at the bottom right.
+const DEPTH_RAMP_WGSL: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, (uv.x + uv.y) * 0.5, 1.0);
+}
+";
+
+/// Resources the cases draw with.
+struct Fixture<'a> {
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    source: OnceCell<wgpu::TextureView>,
+    blitter: blitter_new::Blitter,
+}
+
+impl<'a> Fixture<'a> {
+    fn new(device: &'a wgpu::Device, queue: &'a wgpu::Queue) -> Self {
+        Self {
+            device,
+            queue,
+            source: OnceCell::new(),
+            blitter: blitter_new::Blitter::new(device),
+        }
+    }
+
+    /// Created on first use, as the tiled case's device can't hold it.
+    fn source(&self) -> &wgpu::TextureView {
+        self.source.get_or_init(|| {
+            self.device
+                .create_texture_with_data(
+                    self.queue,
+                    &source_descriptor("Source", 1),
+                    &source_rgba8(),
+                )
+                .create_view(&Default::default())
+        })
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        blit: Blit,
+        target: BlitTarget,
+        viewport: (f32, f32, f32, f32),
+    ) -> anyhow::Result<()> {
+        let (device, blitter) = (self.device, &self.blitter);
+        match blit {
+            Blit::Old(space, _) => {
+                blitter_old::Blitter::new(device, self.source(), space, target.format)?
+                    .blit_with_viewport(encoder, target.view, viewport)
+            }
+            Blit::New => blitter.blit_to_texture(
+                encoder,
+                device,
+                self.source(),
+                target.view,
+                target.format,
+                viewport,
+            ),
+            Blit::Tiled => {
+                let tiled = TiledTexture::new(
+                    device,
+                    self.queue,
+                    &source_descriptor("Tiled Source", 1),
+                    &source_rgba8(),
+                );
+                let options = BlitOptions::default();
+                blitter.blit_tiled(encoder, device, &tiled, target, &options, viewport);
+            }
+            Blit::Options(options) => {
+                let lut = Lut3d::new(device, self.queue, &grading_lut());
+                let options = options(&lut);
+                blitter.blit_with_options(
+                    encoder,
+                    device,
+                    self.source(),
+                    target,
+                    &options,
+                    viewport,
+                );
+            }
+            Blit::Resolve(filter) => {
+                let multisampled = device.create_texture(&wgpu::TextureDescriptor {
+                    sample_count: 4,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    ..source_descriptor("Multisampled", 1)
+                });
+                let view = multisampled.create_view(&Default::default());
+                // Half-pixel edges leave samples either side of them.
+                let inset = (1.5, 2.5, 12., 11.);
+                blitter.blit_to_target(
+                    encoder,
+                    device,
+                    self.source(),
+                    BlitTarget::new(&view, TextureFormat::Rgba8Unorm).multisampled(4, None),
+                    inset,
+                );
+                blitter.resolve_to_target(encoder, device, &multisampled, filter, target, viewport);
+            }
+            Blit::Depth(vis) => {
+                let depth = self.depth_ramp(encoder);
+                blitter.visualise_depth(encoder, device, &depth, vis, target, viewport);
+            }
+            Blit::Integer(mode) => {
+                let ids: Vec<u8> = (0..SOURCE_SIZE * SOURCE_SIZE)
+                    .map(|i| (i % SOURCE_SIZE) / 4 + i / SOURCE_SIZE / 4 * 4)
+                    .flat_map(u32::to_le_bytes)
+                    .collect();
+                let texture = device.create_texture_with_data(
+                    self.queue,
+                    &wgpu::TextureDescriptor {
+                        format: TextureFormat::R32Uint,
+                        ..source_descriptor("Ids", 1)
+                    },
+                    &ids,
+                );
+                blitter.blit_integer(encoder, device, &texture, mode, target, viewport);
+            }
+            Blit::Yuv(layout) => {
+                let frame = YuvFrame::new(device, SOURCE_SIZE, SOURCE_SIZE, layout);
+                let format = YuvFormat::default();
+                blitter.encode_yuv(
+                    encoder,
+                    device,
+                    self.source(),
+                    &format,
+                    ChromaFilter::Linear,
+                    &frame,
+                );
+                blitter.blit_yuv(encoder, device, frame.planes(), &format, target, viewport);
+            }
+            Blit::Subresource => {
+                let layers: Vec<u8> = std::iter::repeat_n(0, source_rgba8().len())
+                    .chain(source_rgba8())
+                    .collect();
+                let array = device.create_texture_with_data(
+                    self.queue,
+                    &source_descriptor("Layers", 2),
+                    &layers,
+                );
+                let mips = device.create_texture(&wgpu::TextureDescriptor {
+                    mip_level_count: 2,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    ..source_descriptor("Mips", 1)
+                });
+                blitter.blit_subresource(
+                    encoder,
+                    device,
+                    &array,
+                    Subresource::new(0, 1),
+                    &mips,
+                    Subresource::new(1, 0),
+                );
+                let mip = mips.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: 1,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                });
+                blitter.blit_to_texture(
+                    encoder,
+                    device,
+                    &mip,
+                    target.view,
+                    target.format,
+                    viewport,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn depth_ramp(&self, encoder: &mut wgpu::CommandEncoder) -> wgpu::Texture {
+        let device = self.device;
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            format: TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ..source_descriptor("Depth", 1)
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Depth Ramp"),
+            source: wgpu::ShaderSource::Wgsl(DEPTH_RAMP_WGSL.into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth Ramp"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: Default::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: Default::default(),
+            fragment: None,
+            multiview: None,
+        });
+        let view = depth.create_view(&Default::default());
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Ramp"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        pass.set_pipeline(&pipeline);
+        pass.draw(0..3, 0..1);
+        drop(pass);
+        depth
+    }
+}
+
+impl Case {
+    fn tolerance(&self) -> Tolerance {
+        Tolerance {
+            max_abs: self.max_abs,
+            ..Default::default()
+        }
+    }
+
+    /// The CPU reference, for the cases `reference` can mirror.
+    fn expected(&self) -> Option<Image> {
+        let src = Image::from_rgba8(SOURCE_SIZE, SOURCE_SIZE, &source_rgba8(), false);
+        let (mode, conversion) = match self.blit {
+            Blit::Old(_, conversion) => (AddressMode::ClampToEdge, conversion),
+            Blit::New => (AddressMode::Repeat, Conversion::None),
+            // Tiles clamp at the image's edges and read their borders inside.
+            Blit::Tiled => (AddressMode::ClampToEdge, Conversion::None),
+            _ => return None,
+        };
+        let mut target = Image::new(self.size.0, self.size.1);
+        reference::blit(&src, &mut target, self.viewport, mode, conversion);
+        // Goldens hold what's stored, so `*Srgb` targets are encoded.
+        let stored = target.to_rgba8(self.format.is_srgb());
+        Some(Image::from_rgba8(self.size.0, self.size.1, &stored, false))
+    }
+
+    fn render(&self, devices: &Devices) -> anyhow::Result<Image> {
+        let (device, queue) = match self.blit {
+            Blit::Tiled => devices
+                .small
+                .as_ref()
+                .map(|(device, queue)| (device, queue))
+                .context("No device with 8x8 textures")?,
+            _ => (&devices.device, &devices.queue),
+        };
+        let fixture = Fixture::new(device, queue);
+        let mut drawn = Ok(());
+        let image = golden::render(device, queue, self.size, self.format, |encoder, target| {
+            let target = BlitTarget::new(target, self.format);
+            drawn = fixture.draw(encoder, self.blit, target, self.viewport);
+        })?;
+        drawn.map(|()| image)
+    }
+}
+
+struct Devices {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    /// With textures of at most 8x8, for tiling.
+    small: Option<(wgpu::Device, wgpu::Queue)>,
+}
+
+impl Devices {
+    fn new() -> Option<Self> {
+        let (device, queue) = golden::headless_device()?;
+        let small = golden::headless_device_with_limits(wgpu::Limits {
+            max_texture_dimension_2d: 8,
+            ..Default::default()
+        });
+        Some(Self {
+            device,
+            queue,
+            small,
+        })
+    }
+}
+
+#[test]
+fn goldens_match_reference() {
+    if std::env::var_os(golden::UPDATE_ENV).is_some() {
+        return;
+    }
+    for case in CASES {
+        let Some(expected) = case.expected() else {
+            continue;
+        };
+        let path = golden_dir().join(format!("{}.png", case.name));
+        let image = image::open(&path)
+            .unwrap_or_else(|error| panic!("{}: {error}", path.display()))
+            .into_rgba8();
+        let golden = Image::from_rgba8(image.width(), image.height(), &image, false);
+        let metrics = golden::metrics(&expected, &golden);
+        assert!(
+            case.tolerance().accepts(&metrics),
+            "{} drifted from the reference: {metrics:?}",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn renders_match_goldens() {
+    let Some(devices) = Devices::new() else {
+        eprintln!("No adapter, skipping golden renders");
+        return;
+    };
+    let failures: Vec<_> = CASES
+        .iter()
+        .filter_map(|case| match case.render(&devices) {
+            Err(error) => Some(error.context(case.name)),
+            Ok(image) => golden::check(&golden_dir(), case.name, &image, case.tolerance()).err(),
+        })
+        .collect();
+    assert!(failures.is_empty(), "{failures:#?}");
+}
+
+/// A `Linear` options blit into a float target keeps values above 1.
+#[test]
+fn options_keep_hdr_in_float_targets() {
+    let Some((device, queue)) = golden::headless_device() else {
+        eprintln!("No adapter, skipping the HDR options blit");
+        return;
+    };
+    let format = TextureFormat::Rgba16Float;
+    let texel = |values: [f32; 4]| -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|&v| half::f16::from_f32(v).to_le_bytes())
+            .collect()
+    };
+    let one_texel = |label, usage| wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        format,
+        usage,
+        ..source_descriptor(label, 1)
+    };
+    let src = device.create_texture_with_data(
+        &queue,
+        &one_texel("HDR Source", wgpu::TextureUsages::TEXTURE_BINDING),
+        &texel([4., 0.5, 2., 1.]),
+    );
+    let dst = device.create_texture(&one_texel(
+        "HDR Target",
+        wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    ));
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("HDR Readback"),
+        size: 8,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    let view = dst.create_view(&Default::default());
+    blitter_new::Blitter::new(&device).blit_with_options(
+        &mut encoder,
+        &device,
+        &src.create_view(&Default::default()),
+        BlitTarget::new(&view, format),
+        &BlitOptions {
+            exposure: 1.,
+            ..Default::default()
+        },
+        (0., 0., 1., 1.),
+    );
+    encoder.copy_texture_to_buffer(
+        dst.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &readback,
+            layout: Default::default(),
+        },
+        dst.size(),
+    );
+    queue.submit([encoder.finish()]);
+    readback
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    assert_eq!(
+        *readback.slice(..).get_mapped_range(),
+        texel([8., 1., 4., 1.])
+    );
+}
+
+/// Ids are 4x4 source blocks, so id 0 covers the top-left 8x8 of the
+/// target.
+#[test]
+fn integer_hash_keeps_zero_black() {
+    let Some(devices) = Devices::new() else {
+        eprintln!("No adapter, skipping golden renders");
+        return;
+    };
+    let case = CASES
+        .iter()
+        .find(|case| case.name == "integer_hash")
+        .unwrap();
+    let image = case.render(&devices).unwrap();
+    for (x, y) in (0..8).flat_map(|y| (0..8).map(move |x| (x, y))) {
+        assert_eq!(image.texel(x, y), [0., 0., 0., 1.], "({x}, {y})");
+    }
+}
+
+/// Single-channel loads come back as (id, 0, 0, 1), which mustn't stop id 0
+/// from hashing to black.
+#[test]
+fn hash_keeps_zero_black() {
+    let Some((device, queue)) = golden::headless_device() else {
+        eprintln!("No adapter, skipping the integer hash");
+        return;
+    };
+    let blitter = blitter_new::Blitter::new(&device);
+    let ids = [0u32, 7];
+    for (format, data) in [
+        (
+            TextureFormat::R32Uint,
+            ids.iter()
+                .flat_map(|id| id.to_le_bytes())
+                .collect::<Vec<_>>(),
+        ),
+        (
+            TextureFormat::R16Uint,
+            ids.iter()
+                .flat_map(|&id| (id as u16).to_le_bytes())
+                .collect(),
+        ),
+    ] {
+        let texture = device.create_texture_with_data(
+            &queue,
+            &wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: 2,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                format,
+                ..source_descriptor("Ids", 1)
+            },
+            &data,
+        );
+        let target_format = TextureFormat::Rgba8Unorm;
+        let image = golden::render(&device, &queue, (2, 1), target_format, |encoder, view| {
+            blitter.blit_integer(
+                encoder,
+                &device,
+                &texture,
+                IntegerBlit::HashColour,
+                BlitTarget::new(view, target_format),
+                (0., 0., 2., 1.),
+            );
+        })
+        .unwrap();
+        assert_eq!(image.texel(0, 0), [0., 0., 0., 1.], "{format:?}: id 0");
+        assert_ne!(image.texel(1, 0), [0., 0., 0., 1.], "{format:?}: id 7");
+    }
+}
+
+/// `encode_rgb9e5` against the CPU encoder. GLES can't read `Rgb9e5Ufloat`
+/// back, so the packed texture is blitted like any source; the 8-bit target
+/// hides rounding, but not packing mistakes.
+#[test]
+fn rgb9e5_matches_cpu() {
+    let Some((device, queue)) = golden::headless_device() else {
+        eprintln!("No adapter, skipping the RGB9E5 encode");
+        return;
+    };
+    // Exponents from 2^-15 up to 1, with channels sharing them unevenly.
+    let values: Vec<[f32; 3]> = (0..SOURCE_SIZE * SOURCE_SIZE)
+        .map(|i| {
+            let v = (i as f32 / 17. - 15.).exp2();
+            [v, v * 0.37, 1. - v].map(|v| half::f16::from_f32(v).to_f32())
+        })
+        .collect();
+    let data: Vec<u8> = values
+        .iter()
+        .flat_map(|&[r, g, b]| [r, g, b, 1.])
+        .flat_map(|v| half::f16::from_f32(v).to_le_bytes())
+        .collect();
+    let src = device.create_texture_with_data(
+        &queue,
+        &wgpu::TextureDescriptor {
+            format: TextureFormat::Rgba16Float,
+            ..source_descriptor("Float Source", 1)
+        },
+        &data,
+    );
+    let packed = device.create_texture(&wgpu::TextureDescriptor {
+        format: TextureFormat::Rgb9e5Ufloat,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        ..source_descriptor("RGB9E5", 1)
+    });
+    let blitter = blitter_new::Blitter::new(&device);
+    let format = TextureFormat::Rgba8Unorm;
+    let actual = golden::render(
+        &device,
+        &queue,
+        (SOURCE_SIZE, SOURCE_SIZE),
+        format,
+        |encoder, target| {
+            blitter.encode_rgb9e5(
+                encoder,
+                &device,
+                &src.create_view(&Default::default()),
+                &packed,
+            );
+            blitter.blit_to_texture(
+                encoder,
+                &device,
+                &packed.create_view(&Default::default()),
+                target,
+                format,
+                (0., 0., SOURCE_SIZE as f32, SOURCE_SIZE as f32),
+            );
+        },
+    )
+    .unwrap();
+
+    let mut decoded = values
+        .iter()
+        .map(|&rgb| rgbe::decode_rgb9e5(rgbe::encode_rgb9e5(rgb)));
+    let expected = Image::from_fn(SOURCE_SIZE, SOURCE_SIZE, |_, _| {
+        let [r, g, b] = decoded.next().unwrap();
+        [r, g, b, 1.]
+    });
+    let stored = expected.to_rgba8(false);
+    let expected = Image::from_rgba8(SOURCE_SIZE, SOURCE_SIZE, &stored, false);
+    reference::assert_close(&expected, &actual, 1. / 255.);
+}
+
+#[test]
+fn metrics_rank_differences() {
+    let a = Image::from_fn(16, 16, |x, y| [x as f32 / 15., y as f32 / 15., 0.5, 1.]);
+    let identical = golden::metrics(&a, &a);
+    assert_eq!(identical.max_abs, 0.);
+    assert!(identical.psnr.is_infinite());
+    assert!((identical.ssim - 1.).abs() < 1e-6);
+
+    let mut one_step = a.clone();
+    let [r, g, b, alpha] = a.texel(3, 3);
+    one_step.set_texel(3, 3, [r, g, b + 1. / 255., alpha]);
+    let noisy = Image::from_fn(16, 16, |x, y| {
+        let noise = if (x + y) % 2 == 0 { 0.2 } else { -0.2 };
+        let [r, g, b, a] = a.texel(x, y);
+        [r + noise, g + noise, b + noise, a]
+    });
+    let small = golden::metrics(&a, &one_step);
+    let large = golden::metrics(&a, &noisy);
+    assert!(small.psnr > large.psnr && small.ssim > large.ssim);
+    assert!(!Tolerance::default().accepts(&large));
+}
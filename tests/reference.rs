@@ -0,0 +1,93 @@
+use blittin_test::{
+    reference::{self, Conversion, Image},
+    rgbe,
+};
+use wgpu::AddressMode;
+
+fn checker(width: u32, height: u32) -> Image {
+    Image::from_fn(width, height, |x, y| {
+        let v = ((x + y) % 2) as f32;
+        [v, 1. - v, x as f32 / width as f32, 1.]
+    })
+}
+
+#[test]
+fn one_to_one_blit_reproduces_source() {
+    let src = checker(8, 6);
+    let mut target = Image::new(8, 6);
+    for mode in [
+        AddressMode::ClampToEdge,
+        AddressMode::Repeat,
+        AddressMode::MirrorRepeat,
+    ] {
+        reference::blit(&src, &mut target, (0., 0., 8., 6.), mode, Conversion::None);
+        reference::assert_close(&src, &target, 1e-6);
+    }
+}
+
+#[test]
+fn viewport_only_covers_pixel_centres_inside_it() {
+    let src = Image::from_fn(1, 1, |_, _| [1.; 4]);
+    let mut target = Image::new(4, 4);
+    reference::blit(
+        &src,
+        &mut target,
+        (1., 1., 1.6, 2.),
+        AddressMode::ClampToEdge,
+        Conversion::None,
+    );
+    let drawn: Vec<_> = (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .filter(|&(x, y)| target.texel(x, y)[0] == 1.)
+        .collect();
+    assert_eq!(drawn, [(1, 1), (2, 1), (1, 2), (2, 2)]);
+}
+
+#[test]
+fn bilinear_blends_neighbours_and_respects_address_mode() {
+    let src = Image::from_fn(2, 1, |x, _| [x as f32, 0., 0., 1.]);
+    let sample = |u, mode| reference::sample_bilinear(&src, [u, 0.5], mode)[0];
+    assert_eq!(sample(0.5, AddressMode::ClampToEdge), 0.5);
+    // Left of the first texel centre: clamping holds it, repeating wraps to
+    // the last texel and the border fades to black.
+    assert_eq!(sample(0., AddressMode::ClampToEdge), 0.);
+    assert_eq!(sample(0., AddressMode::Repeat), 0.5);
+    assert_eq!(sample(0., AddressMode::MirrorRepeat), 0.);
+    assert_eq!(sample(1., AddressMode::ClampToBorder), 0.5);
+}
+
+#[test]
+fn srgb_round_trips_every_8_bit_value() {
+    for i in 0..=255u8 {
+        let v = i as f32 / 255.;
+        let round_trip = reference::linear_to_srgb(reference::srgb_to_linear(v));
+        assert!((round_trip - v).abs() < 1e-5, "{i}: {round_trip}");
+    }
+    let image = Image::from_rgba8(1, 1, &[0, 128, 255, 255], true);
+    assert_eq!(image.to_rgba8(true), [0, 128, 255, 255]);
+}
+
+#[test]
+fn rgbe_decode_matches_cpu_encoding() {
+    for rgb in [[1., 0.5, 0.25], [1000., 3., 0.], [1e-3, 2e-3, 4e-3]] {
+        let encoded = rgbe::encode_rgbe(rgb);
+        let texel = encoded.map(|v| v as f32 / 255.);
+        let decoded = Conversion::RgbeToLinear.apply(texel);
+        let expected = rgbe::decode_rgbe(encoded);
+        for c in 0..3 {
+            let relative = (decoded[c] - expected[c]).abs() / expected[c].max(1e-6);
+            assert!(relative < 1e-5, "{rgb:?}: {decoded:?} vs {expected:?}");
+        }
+    }
+}
+
+#[test]
+fn difference_reports_worst_pixel() {
+    let expected = Image::new(3, 2);
+    let mut actual = expected.clone();
+    actual.set_texel(2, 1, [0., 0.25, 0., 0.]);
+    let diff = reference::difference(&expected, &actual);
+    assert_eq!(diff.worst, (2, 1));
+    assert_eq!(diff.max_abs, 0.25);
+    assert!(diff.within(0.25) && !diff.within(0.2));
+}
@@ -0,0 +1,236 @@
+//! Parses and validates the shipped WGSL with naga and checks the entry points
+//! the Rust side asks for, without needing a GPU.
+
+use naga::{valid, Binding, BuiltIn, ScalarKind, ShaderStage, TypeInner};
+
+const BLIT_NEW: &str = include_str!("../src/blit_new.wgsl");
+const BLIT_OLD: &str = include_str!("../src/blit_old.wgsl");
+const TRIG: &str = include_str!("../src/trig.wgsl");
+
+/// Each shader with the Rust source that names its entry points.
+const SHADERS: [(&str, &str, &str); 3] = [
+    (
+        "blit_new.wgsl",
+        BLIT_NEW,
+        include_str!("../src/blitter_new.rs"),
+    ),
+    (
+        "blit_old.wgsl",
+        BLIT_OLD,
+        include_str!("../src/blitter_old.rs"),
+    ),
+    ("trig.wgsl", TRIG, include_str!("../src/main.rs")),
+];
+
+const VERTEX_IN: &[&str] = &["@builtin(vertex_index) u32"];
+const VERTEX_OUT: &[&str] = &["@builtin(position) vec4<f32>", "@location(0) vec2<f32>"];
+const COLOUR_OUT: &[&str] = &["@location(0) vec4<f32>"];
+
+struct Expected {
+    name: &'static str,
+    stage: ShaderStage,
+    inputs: &'static [&'static str],
+    outputs: &'static [&'static str],
+}
+
+const fn vertex(name: &'static str) -> Expected {
+    Expected {
+        name,
+        stage: ShaderStage::Vertex,
+        inputs: VERTEX_IN,
+        outputs: VERTEX_OUT,
+    }
+}
+
+const fn fragment(name: &'static str, outputs: &'static [&'static str]) -> Expected {
+    Expected {
+        name,
+        stage: ShaderStage::Fragment,
+        inputs: VERTEX_OUT,
+        outputs,
+    }
+}
+
+fn expected(shader: &str) -> Vec<Expected> {
+    match shader {
+        "blit_new.wgsl" => vec![
+            vertex("vs_main"),
+            vertex("vs_main_tile"),
+            fragment("fs_main", COLOUR_OUT),
+            fragment("fs_main_options", COLOUR_OUT),
+            fragment("fs_main_options_lut", COLOUR_OUT),
+            fragment("fs_main_3d", COLOUR_OUT),
+            fragment("fs_main_resolve_box", COLOUR_OUT),
+            fragment("fs_main_resolve_tonemapped", COLOUR_OUT),
+            fragment("fs_main_depth", COLOUR_OUT),
+            fragment("fs_main_uint_copy", &["@location(0) vec4<u32>"]),
+            fragment("fs_main_uint_hash", COLOUR_OUT),
+            fragment("fs_main_sint_copy", &["@location(0) vec4<i32>"]),
+            fragment("fs_main_sint_hash", COLOUR_OUT),
+            fragment("fs_main_nv12", COLOUR_OUT),
+            fragment("fs_main_i420", COLOUR_OUT),
+            fragment("fs_main_encode_y", COLOUR_OUT),
+            fragment("fs_main_encode_uv", COLOUR_OUT),
+            fragment("fs_main_encode_v", COLOUR_OUT),
+            Expected {
+                name: "cs_main_rgb9e5",
+                stage: ShaderStage::Compute,
+                inputs: &["@builtin(global_invocation_id) vec3<u32>"],
+                outputs: &[],
+            },
+        ],
+        "blit_old.wgsl" => vec![
+            vertex("vs_main"),
+            fragment("fs_main", COLOUR_OUT),
+            fragment("fs_main_linear_to_srgb", COLOUR_OUT),
+            fragment("fs_main_rgbe_to_linear", COLOUR_OUT),
+            fragment("fs_main_srgb_to_linear", COLOUR_OUT),
+        ],
+        "trig.wgsl" => vec![
+            vertex("vs_main_full"),
+            vertex("vs_main"),
+            fragment("fs_main", COLOUR_OUT),
+        ],
+        _ => unreachable!(),
+    }
+}
+
+fn validate(name: &str, source: &str) -> naga::Module {
+    let module = naga::front::wgsl::parse_str(source)
+        .unwrap_or_else(|error| panic!("{}", error.emit_to_string_with_path(source, name)));
+    valid::Validator::new(valid::ValidationFlags::all(), valid::Capabilities::empty())
+        .validate(&module)
+        .unwrap_or_else(|error| panic!("{}", error.emit_to_string_with_path(source, name)));
+    module
+}
+
+fn describe_type(inner: &TypeInner) -> String {
+    let scalar = |kind, width| match (kind, width) {
+        (ScalarKind::Float, 4) => "f32".to_owned(),
+        (ScalarKind::Uint, 4) => "u32".to_owned(),
+        (ScalarKind::Sint, 4) => "i32".to_owned(),
+        (ScalarKind::Bool, _) => "bool".to_owned(),
+        _ => format!("{kind:?}{width}"),
+    };
+    match *inner {
+        TypeInner::Scalar { kind, width } => scalar(kind, width),
+        TypeInner::Vector { size, kind, width } => {
+            format!("vec{}<{}>", size as u8, scalar(kind, width))
+        }
+        ref other => format!("{other:?}"),
+    }
+}
+
+/// Flattens an argument or result into `@binding type` strings, one per
+/// struct member.
+fn describe_io(
+    module: &naga::Module,
+    ty: naga::Handle<naga::Type>,
+    binding: Option<&Binding>,
+) -> Vec<String> {
+    let inner = &module.types[ty].inner;
+    let Some(binding) = binding else {
+        let TypeInner::Struct { members, .. } = inner else {
+            panic!("Unbound entry point I/O of type {inner:?}");
+        };
+        return members
+            .iter()
+            .flat_map(|member| describe_io(module, member.ty, member.binding.as_ref()))
+            .collect();
+    };
+    let binding = match binding {
+        Binding::BuiltIn(BuiltIn::Position { .. }) => "@builtin(position)".to_owned(),
+        Binding::BuiltIn(BuiltIn::VertexIndex) => "@builtin(vertex_index)".to_owned(),
+        Binding::BuiltIn(BuiltIn::GlobalInvocationId) => {
+            "@builtin(global_invocation_id)".to_owned()
+        }
+        Binding::BuiltIn(other) => format!("@builtin({other:?})"),
+        Binding::Location { location, .. } => format!("@location({location})"),
+    };
+    vec![format!("{binding} {}", describe_type(inner))]
+}
+
+#[test]
+fn shaders_validate() {
+    for (name, source, _) in SHADERS {
+        validate(name, source);
+    }
+}
+
+#[test]
+fn entry_points_have_expected_stage_and_io() {
+    for (name, source, _) in SHADERS {
+        let module = validate(name, source);
+        for expected in expected(name) {
+            let entry = module
+                .entry_points
+                .iter()
+                .find(|entry| entry.name == expected.name)
+                .unwrap_or_else(|| panic!("{name} has no entry point {}", expected.name));
+            assert_eq!(entry.stage, expected.stage, "{name}: {}", expected.name);
+
+            let inputs: Vec<_> = entry
+                .function
+                .arguments
+                .iter()
+                .flat_map(|arg| describe_io(&module, arg.ty, arg.binding.as_ref()))
+                .collect();
+            assert_eq!(inputs, expected.inputs, "{name}: {} inputs", expected.name);
+
+            let outputs: Vec<_> = entry
+                .function
+                .result
+                .iter()
+                .flat_map(|result| describe_io(&module, result.ty, result.binding.as_ref()))
+                .collect();
+            assert_eq!(
+                outputs, expected.outputs,
+                "{name}: {} outputs",
+                expected.name
+            );
+        }
+    }
+}
+
+#[test]
+fn rgb9e5_workgroup_matches_dispatch() {
+    let module = validate("blit_new.wgsl", BLIT_NEW);
+    let entry = module
+        .entry_points
+        .iter()
+        .find(|entry| entry.name == "cs_main_rgb9e5")
+        .unwrap();
+    // `encode_rgb9e5` dispatches `div_ceil(8)` groups in x and y.
+    assert_eq!(entry.workgroup_size, [8, 8, 1]);
+}
+
+/// String literals in `rust` that look like entry point names.
+fn entry_points_named_in(rust: &str) -> Vec<&str> {
+    rust.split('"')
+        .skip(1)
+        .step_by(2)
+        .filter(|literal| {
+            ["vs_main", "fs_main", "cs_main"]
+                .iter()
+                .any(|prefix| literal.starts_with(prefix))
+                && literal
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .collect()
+}
+
+#[test]
+fn every_entry_point_named_in_rust_is_checked() {
+    for (name, _, rust) in SHADERS {
+        let named = entry_points_named_in(rust);
+        assert!(!named.is_empty(), "Found no entry points for {name}");
+        let expected = expected(name);
+        for entry_point in named {
+            assert!(
+                expected.iter().any(|e| e.name == entry_point),
+                "{entry_point} is used with {name} but not checked here"
+            );
+        }
+    }
+}